@@ -6,16 +6,23 @@
 
 //! Items to be launched by an app.
 
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
 use gio::prelude::*;
 use glib::{MainContext, VariantDict};
 use indexmap::IndexMap;
 use libc::pid_t;
 use log::{debug, error, info, trace, warn};
+use serde::Deserialize;
 use zbus::dbus_interface;
 use zbus::export::zvariant;
 
+use crate::history::{record_and_save_later, History};
 use crate::matching::*;
 use crate::systemd::{start_app_scope, AsyncSystemd1ManagerProxy, ScopeProperties};
 use zbus::export::zvariant::OwnedObjectPath;
@@ -72,9 +79,12 @@ pub fn create_launch_context(
 ) -> gio::AppLaunchContext {
     let launch_context = gio::AppLaunchContext::new();
     launch_context.connect_launched(move |_, app, platform_data| {
+        // Apps created from a command line have no ID; we create these with the ID of the
+        // original app as name.
+        let id = app.id().unwrap_or_else(|| app.name()).to_string();
         trace!(
             "App {} launched with platform_data: {:?}",
-            app.id().unwrap(),
+            id,
             platform_data
         );
         match platform_data
@@ -89,8 +99,8 @@ pub fn create_launch_context(
             Some(pid) => {
                 let c = connection.clone();
                 let s = scope_settings.clone();
-                info!("App {} launched with PID {}", app.id().unwrap(), pid);
-                let id = app.id().unwrap().to_string();
+                info!("App {} launched with PID {}", id, pid);
+                let id = id.clone();
                 let description = app.description().map_or_else(
                     || format!("app started by {}", scope_settings.started_by),
                     |value| format!("{} started by {}", value, scope_settings.started_by),
@@ -116,72 +126,312 @@ pub fn create_launch_context(
 }
 
 /// A target for launching an app.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AppLaunchTarget {
     /// A URI to launch.
     Uri(String),
     /// A file to launch.
     File(String),
+    /// A file inside the directory of another item, found by a file search, to launch at a
+    /// line if any.
+    ItemFile(String, Option<u32>),
 }
 
 impl AppLaunchTarget {
     /// The description for this launch target.
-    fn description(&self) -> &str {
+    pub fn description(&self) -> &str {
         match self {
             AppLaunchTarget::Uri(uri) => uri,
             AppLaunchTarget::File(path) => path,
+            AppLaunchTarget::ItemFile(path, _) => path,
         }
     }
 }
 
-/// A recent item from the file system.
+/// A search for a file in an item, e.g. `mdcat:src/main.rs:42`.
 #[derive(Debug, PartialEq)]
+struct FileSearch<'a> {
+    /// The search term for the item.
+    item: &'a str,
+    /// The path of the file, relative to the item.
+    path: &'a str,
+    /// The line to open the file at.
+    line: Option<u32>,
+}
+
+impl<'a> FileSearch<'a> {
+    /// Parse a file search from the given search `terms`.
+    ///
+    /// A file search is a single term of the form `item:path` or `item:path:line`, where `path`
+    /// is relative and stays inside the item, i.e. has no `..` components.  Terms which look
+    /// like query filters, e.g. `name:mdcat` or `-path:src`, are no file searches.
+    fn parse<S: AsRef<str>>(terms: &'a [S]) -> Option<FileSearch<'a>> {
+        let term = match terms {
+            [term] => term.as_ref(),
+            _ => return None,
+        };
+        let (item, rest) = term.split_once(':')?;
+        let (path, line) = match rest.rsplit_once(':') {
+            Some((path, line)) => (path, Some(u32::from_str(line).ok()?)),
+            None => (rest, None),
+        };
+        let is_filter = matches!(item, "name" | "path" | "ide") || item.starts_with('-');
+        let inside_item = Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if item.is_empty() || is_filter || path.is_empty() || !inside_item {
+            None
+        } else {
+            Some(FileSearch { item, path, line })
+        }
+    }
+
+    /// The name of the result for this search.
+    fn name(&self) -> String {
+        match self.line {
+            Some(line) => format!("{}:{}", self.path, line),
+            None => self.path.to_string(),
+        }
+    }
+}
+
+/// Make the command line to open `path` at `line` from the `exec` line of an app.
+///
+/// Replace the first file or URL field code in `exec` with `--line <line> <path>`, which is
+/// how Jetbrains IDEs take a line to open a file at, or append these arguments if `exec` has
+/// no such field code.  Put `--line <line>` in front of flatpak's `@@` file forwarding markers.
+///
+/// Split `exec` into arguments with the quoting rules of desktop files, and quote every
+/// argument of the new command line; keep other field codes and escaped `%%` in arguments
+/// as they are, to let Gio expand them.  Fail if `exec` has invalid quoting.
+fn commandline_at_line(exec: &str, path: &str, line: u32) -> Result<String, glib::Error> {
+    let mut args: Vec<String> = if exec.trim().is_empty() {
+        Vec::new()
+    } else {
+        glib::shell_parse_argv(exec)?
+            .into_iter()
+            .map(|arg| glib::shell_quote(arg).to_string_lossy().to_string())
+            .collect()
+    };
+    let path = glib::shell_quote(path).to_string_lossy().replace('%', "%%");
+    let line_args = ["--line".to_string(), line.to_string()];
+    let field_code = args
+        .iter()
+        .position(|arg| ["'%f'", "'%F'", "'%u'", "'%U'"].contains(&arg.as_str()));
+    match field_code {
+        Some(index) => {
+            args[index] = path;
+            let index = if 0 < index && args[index - 1].starts_with("'@@") {
+                index - 1
+            } else {
+                index
+            };
+            args.splice(index..index, line_args);
+        }
+        None => {
+            args.extend(line_args);
+            args.push(path);
+        }
+    }
+    Ok(args.join(" "))
+}
+
+/// A recent item from the file system.
+#[derive(Debug, Clone, PartialEq)]
 pub struct AppLaunchItem {
     /// The human readable name
     pub name: String,
 
     /// The target to launch when activating this item.
     pub target: AppLaunchTarget,
+
+    /// When this item was last used, if known.
+    pub last_used: Option<SystemTime>,
+
+    /// Additional keywords to match this item against, e.g. groups or tags.
+    pub keywords: Vec<String>,
+
+    /// A description to show for this item instead of the description of its target.
+    pub description: Option<String>,
+
+    /// An icon file to show for this item instead of the icon of the app.
+    pub icon: Option<PathBuf>,
+
+    /// The app to launch this item with instead of the app of the search provider.
+    pub app: Option<gio::DesktopAppInfo>,
+}
+
+impl AppLaunchItem {
+    /// The description for this item.
+    fn description(&self) -> &str {
+        self.description
+            .as_deref()
+            .unwrap_or_else(|| self.target.description())
+    }
+
+    /// The app to launch this item with, falling back to the given `default` app.
+    fn app<'a>(&'a self, default: &'a gio::DesktopAppInfo) -> &'a gio::DesktopAppInfo {
+        self.app.as_ref().unwrap_or(default)
+    }
+}
+
+/// Get the text to match `ide:` terms against for items launched with `app`.
+///
+/// This is the name and the desktop ID of the app, e.g. `Rider jetbrains-rider.desktop`.
+pub fn app_match_text(app: &gio::DesktopAppInfo) -> String {
+    format!(
+        "{} {}",
+        app.name(),
+        app.id().map(|id| id.to_string()).unwrap_or_default()
+    )
+}
+
+/// The maximum score an item gets for having been used just now.
+///
+/// This is less than the score of a single term matching the name of an item, so a recently
+/// used item never outranks an item whose name matches where its own name doesn't.
+const MAX_RECENCY_SCORE: f64 = 5.0;
+
+/// The score of a single term matching a keyword of an item.
+const KEYWORD_SCORE: f64 = 5.0;
+
+/// The time after which the recency score of an item drops to half.
+const RECENCY_HALF_LIFE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Compute a score for an item last used at `last_used`, relative to `now`.
+///
+/// The score starts at `MAX_RECENCY_SCORE` for items used just now and decays exponentially
+/// with the age of the item.
+fn recency_score(last_used: SystemTime, now: SystemTime) -> f64 {
+    // Items from the future are treated as if used right now.
+    let age = now.duration_since(last_used).unwrap_or_default();
+    MAX_RECENCY_SCORE * 0.5_f64.powf(age.as_secs_f64() / RECENCY_HALF_LIFE.as_secs_f64())
 }
 
 impl ScoreMatchable for AppLaunchItem {
-    /// Compute the score of matching self against `terms`.
+    /// Compute the score of matching self against `terms` with the given `mode`.
     ///
-    /// If all terms match the name each term contributes a score of 10; this makes sure
-    /// that precise matches in the name boost the score somewhat to the top.
+    /// Every term must match the name, any of the keywords or the target, and contributes the
+    /// best score among these:
     ///
-    /// If all terms match the target each term contributes 1 to score, scaled by the relative position
-    /// of the right-most match, assuming that paths typically go from least to most specific segment,
-    /// to the farther to the right a term matches the more specific it was.
-    fn match_score<S: AsRef<str>>(&self, terms: &[S]) -> f64 {
-        let name = self.name.to_lowercase();
-        let target = match &self.target {
-            AppLaunchTarget::Uri(uri) => uri,
-            AppLaunchTarget::File(file) => file,
-        }
-        .to_lowercase();
-        let name_score = terms.iter().try_fold(0.0, |score, term| {
-            name.contains(&term.as_ref().to_lowercase())
-                .then(|| score + 10.0)
-                .ok_or(())
-        });
-        let target = terms.iter().try_fold(0.0, |score, term| {
-            target
-                .rfind(&term.as_ref().to_lowercase())
-                .ok_or(())
-                .map(|index| score + 1.0 * (index as f64 / target.len() as f64))
-        });
-        let score = name_score.unwrap_or_default() + target.unwrap_or_default();
+    /// - A term matching the name scores 10; this makes sure that precise matches in the name
+    ///   boost the score somewhat to the top.
+    /// - A term matching a keyword scores 5.
+    /// - A term matching the target scores 1, scaled by the relative position of the right-most
+    ///   match, assuming that paths typically go from least to most specific segment, so the
+    ///   farther to the right a term matches the more specific it was.
+    ///
+    /// Terms may match different fields, e.g. `work mdcat` matches a project named `mdcat` in
+    /// the group `work`.
+    ///
+    /// In fuzzy mode every term contributes only a fraction of its score, according to the quality
+    /// of the match; in substring mode every match is perfect.
+    ///
+    /// If the item matches at all and it's known when it was last used, add a recency score of at most 5
+    /// which decays with the time since the last use; among items which match equally well this ranks
+    /// recently used items first.
+    fn match_score<S: AsRef<str>>(&self, mode: MatchMode, terms: &[S]) -> f64 {
+        let target = self.target.description();
+        let term_score = |term: &str| {
+            let name = mode.find(&self.name, term).map(|m| 10.0 * m.quality);
+            let keywords = self
+                .keywords
+                .iter()
+                .filter_map(|keyword| mode.find(keyword, term))
+                .map(|m| KEYWORD_SCORE * m.quality);
+            let target = mode.find(target, term).map(|m| m.quality * m.position);
+            name.into_iter()
+                .chain(keywords)
+                .chain(target)
+                .fold(None, |best: Option<f64>, score| {
+                    Some(best.map_or(score, |best| best.max(score)))
+                })
+        };
+        let text_score = terms
+            .iter()
+            .try_fold(0.0, |score, term| {
+                term_score(term.as_ref()).map(|term_score| score + term_score)
+            })
+            .filter(|score| 0.0 < *score);
+        let recency = text_score.map(|_| self.recency_score());
+        let score = text_score.unwrap_or_default() + recency.unwrap_or_default();
         trace!(
-            "Item {:?} matches terms {:?} with score {} (name={:?}, target={:?})",
+            "Item {:?} matches terms {:?} with score {} (text={:?}, recency={:?})",
             self,
             terms.iter().map(|s| s.as_ref()).collect::<Vec<&str>>(),
             score,
-            name_score,
-            target
+            text_score,
+            recency
         );
         score
     }
+
+    /// Get the recency score of this item, or 0 if it's not known when it was last used.
+    fn recency_score(&self) -> f64 {
+        self.last_used
+            .map_or(0.0, |last_used| recency_score(last_used, SystemTime::now()))
+    }
+
+    /// Get the name, the path or the app of this item.
+    ///
+    /// The path is the description of the target; the app is the name of the app to launch
+    /// this item with, if it's not the default app of the search provider.
+    fn field(&self, field: QueryField) -> Option<String> {
+        match field {
+            QueryField::Name => Some(self.name.clone()),
+            QueryField::Path => Some(self.target.description().to_string()),
+            QueryField::App => self.app.as_ref().map(|app| app.name().to_string()),
+        }
+    }
+}
+
+/// What to launch for a search if the search terms do not match exactly one item.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LaunchSearchFallback {
+    /// Launch the app without any item, e.g. to show its welcome screen.
+    #[default]
+    App,
+    /// Launch the best matching item, or the app if no item matches.
+    BestMatch,
+    /// Launch nothing.
+    Nothing,
+}
+
+/// What to launch for a search.
+#[derive(Debug, PartialEq)]
+enum LaunchSearchAction<'a> {
+    /// Launch the item with the given ID.
+    Item(&'a str),
+    /// Launch the app without any item.
+    App,
+    /// Launch nothing.
+    Nothing,
+}
+
+/// Decide what to launch for a search for `query` in `items`.
+///
+/// `items` is an iterator over pairs of `(id, item)`.
+///
+/// Launch the matching item if exactly one item matches `query` with the given `mode`;
+/// otherwise decide according to `fallback`.
+fn launch_search_action<'a, I, T>(
+    items: I,
+    mode: MatchMode,
+    query: &Query,
+    fallback: LaunchSearchFallback,
+) -> LaunchSearchAction<'a>
+where
+    I: Iterator<Item = (&'a str, T)>,
+    T: ScoreMatchable,
+{
+    let matches = find_matching_items(items, mode, query);
+    match (matches.as_slice(), fallback) {
+        ([id], _) => LaunchSearchAction::Item(id),
+        ([id, ..], LaunchSearchFallback::BestMatch) => LaunchSearchAction::Item(id),
+        (_, LaunchSearchFallback::Nothing) => LaunchSearchAction::Nothing,
+        (_, _) => LaunchSearchAction::App,
+    }
 }
 
 /// A search provider for recent items.
@@ -189,21 +439,217 @@ pub struct AppItemSearchProvider<S: ItemsSource<AppLaunchItem>> {
     launch_context: gio::AppLaunchContext,
     app: gio::DesktopAppInfo,
     source: S,
+    match_mode: MatchMode,
+    launch_search_fallback: LaunchSearchFallback,
+    limits: ResultLimits,
+    history: Rc<RefCell<History>>,
     items: IdMap<AppLaunchItem>,
 }
 
 impl<S: ItemsSource<AppLaunchItem>> AppItemSearchProvider<S> {
     /// Create a new search provider for recent items of `app`.
     ///
-    /// Uses the given `source` to load recent items.
-    pub fn new(app: gio::DesktopAppInfo, source: S, launch_context: gio::AppLaunchContext) -> Self {
+    /// Uses the given `source` to load recent items, and matches items against search terms
+    /// with the given `match_mode`.  When launching a search whose terms do not match exactly
+    /// one item, launch according to `launch_search_fallback`.  Return only results within
+    /// the given `limits` to Gnome Shell.
+    ///
+    /// Record activated results in `history`, and rank results which were activated for
+    /// similar searches before higher.
+    pub fn new(
+        app: gio::DesktopAppInfo,
+        source: S,
+        launch_context: gio::AppLaunchContext,
+        match_mode: MatchMode,
+        launch_search_fallback: LaunchSearchFallback,
+        limits: ResultLimits,
+        history: Rc<RefCell<History>>,
+    ) -> Self {
         Self {
             launch_context,
             app,
             source,
+            match_mode,
+            launch_search_fallback,
+            limits,
+            history,
             items: IndexMap::new(),
         }
     }
+
+    /// Rank `scored` results for `terms`.
+    ///
+    /// Discard results below the minimum score, and rank the remaining results with the
+    /// history of activated results.
+    fn rank_results(&self, terms: &[&str], mut scored: Vec<(f64, &str)>) -> Vec<String> {
+        self.limits.retain_min_score(&mut scored);
+        self.history
+            .borrow()
+            .rank(terms, scored, SystemTime::now())
+            .into_iter()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// Pair all `items` with the name and ID of their app, to match `ide:` terms.
+    fn with_apps<'a, K, I>(
+        &'a self,
+        items: I,
+    ) -> impl Iterator<Item = (K, InApp<&'a AppLaunchItem>)>
+    where
+        I: Iterator<Item = (K, &'a AppLaunchItem)> + 'a,
+    {
+        items.map(move |(id, item)| {
+            let app = app_match_text(item.app(&self.app));
+            (id, InApp { item, app })
+        })
+    }
+
+    /// Launch the given `item`.
+    fn launch_item(&self, item: &AppLaunchItem) -> zbus::fdo::Result<()> {
+        let app = item.app(&self.app);
+        info!(
+            "Launching recent item {:?} for {}",
+            item,
+            app.id().unwrap().as_str()
+        );
+        match &item.target {
+            AppLaunchTarget::File(path) => app.launch::<gio::AppLaunchContext>(
+                &[gio::File::for_path(path)],
+                Some(&self.launch_context),
+            ),
+            AppLaunchTarget::Uri(uri) => {
+                Self::uri_handler(app, uri).launch_uris(&[uri], Some(&self.launch_context))
+            }
+            AppLaunchTarget::ItemFile(path, None) => app.launch::<gio::AppLaunchContext>(
+                &[gio::File::for_path(path)],
+                Some(&self.launch_context),
+            ),
+            AppLaunchTarget::ItemFile(path, Some(line)) => Self::line_launcher(app, path, *line)
+                .and_then(|launcher| {
+                    launcher.launch::<gio::AppLaunchContext>(&[], Some(&self.launch_context))
+                }),
+        }
+        .map_err(|error| {
+            error!(
+                "Failed to launch app {} for target {}: {}",
+                app.id().unwrap(),
+                item.target.description(),
+                error,
+            );
+            zbus::fdo::Error::SpawnFailed(format!(
+                "Failed to launch app {} for URI {}: {}",
+                app.id().unwrap(),
+                item.target.description(),
+                error
+            ))
+        })
+    }
+
+    /// Launch the app without any item.
+    fn launch_app(&self) -> zbus::fdo::Result<()> {
+        info!("Launching app {} directly", self.app.id().unwrap().as_str());
+        self.app
+            .launch(&[], Some(&self.launch_context))
+            .map_err(|error| {
+                error!(
+                    "Failed to launch app {}: {:#}",
+                    self.app.id().unwrap(),
+                    error
+                );
+                zbus::fdo::Error::SpawnFailed(format!(
+                    "Failed to launch app {}: {}",
+                    self.app.id().unwrap(),
+                    error
+                ))
+            })
+    }
+
+    /// Get an app which launches `app` to open `path` at `line`.
+    ///
+    /// We cannot pass a line to `app` through Gio, so we make a new app from the command line
+    /// of `app`; the new app has the ID of `app` as name to let the launch context identify it.
+    fn line_launcher(
+        app: &gio::DesktopAppInfo,
+        path: &str,
+        line: u32,
+    ) -> Result<gio::AppInfo, glib::Error> {
+        let exec = app
+            .commandline()
+            .map(|exec| exec.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let commandline = commandline_at_line(&exec, path, line)?;
+        debug!("Launching {} for {}:{}", commandline, path, line);
+        gio::AppInfo::create_from_commandline(
+            commandline,
+            app.id().as_deref(),
+            gio::AppInfoCreateFlags::NONE,
+        )
+    }
+
+    /// Add a result for a file search in `terms`, if any.
+    ///
+    /// If `terms` are a file search like `mdcat:src/main.rs:42` find the best item matching
+    /// `mdcat`, and add a result for `src/main.rs` in the directory of this item, if the file
+    /// exists.  If the item is a file, e.g. a Rider solution, look for `src/main.rs` in the
+    /// directory of that file.  Return the ID of the new result.
+    fn add_file_search_result<T: AsRef<str>>(&mut self, terms: &[T]) -> Option<String> {
+        let search = FileSearch::parse(terms)?;
+        let (item_id, item) = find_matching_items(
+            self.items.iter().filter(|(_, item)| {
+                // Ignore results of previous file searches, and remote items
+                matches!(item.target, AppLaunchTarget::File(_))
+            }),
+            self.match_mode,
+            &Query::parse(&[search.item]),
+        )
+        .into_iter()
+        .next()
+        .and_then(|id| self.items.get_key_value(id))?;
+        let directory = match &item.target {
+            AppLaunchTarget::File(path) if Path::new(path).is_file() => Path::new(path).parent()?,
+            AppLaunchTarget::File(directory) => Path::new(directory),
+            _ => return None,
+        };
+        let file = directory.join(search.path);
+        if !file.is_file() {
+            debug!("File {} of {} does not exist", file.display(), item_id);
+            return None;
+        }
+        let path = file.to_string_lossy().to_string();
+        let id = format!("{}:{}", item_id, search.name());
+        let result = AppLaunchItem {
+            name: search.name(),
+            target: AppLaunchTarget::ItemFile(path, search.line),
+            last_used: None,
+            keywords: Vec::new(),
+            description: Some(format!("{} in {}", search.path, item.name)),
+            icon: None,
+            app: item.app.clone(),
+        };
+        debug!("Adding file result {} for {:?}", id, search);
+        self.items.insert(id.clone(), result);
+        Some(id)
+    }
+
+    /// Get the app to open the given `uri` with.
+    ///
+    /// Use `app` if it handles the scheme of `uri`, otherwise fall back to the default
+    /// handler for the scheme, e.g. Jetbrains Gateway for remote projects.
+    fn uri_handler(app: &gio::DesktopAppInfo, uri: &str) -> gio::AppInfo {
+        let app: gio::AppInfo = app.clone().upcast();
+        match glib::uri_parse_scheme(uri) {
+            Some(scheme) => {
+                let content_type = format!("x-scheme-handler/{}", scheme);
+                if app.supported_types().iter().any(|t| *t == content_type) {
+                    app
+                } else {
+                    gio::AppInfo::default_for_uri_scheme(&scheme).unwrap_or(app)
+                }
+            }
+            None => app,
+        }
+    }
 }
 
 /// The DBus interface of the search provider.
@@ -232,10 +678,17 @@ impl<S: ItemsSource<AppLaunchItem> + 'static> AppItemSearchProvider<S> {
             ))
         })?;
 
-        let ids = find_matching_items(self.items.iter(), terms.as_slice())
-            .into_iter()
-            .map(String::to_owned)
-            .collect();
+        let query = Query::parse(&terms);
+        let scored = find_scored_items(
+            self.with_apps(self.items.iter().map(|(id, item)| (id.as_str(), item))),
+            self.match_mode,
+            &query,
+        );
+        let mut ids = self.rank_results(&terms, scored);
+        if let Some(id) = self.add_file_search_result(&terms) {
+            ids.insert(0, id);
+        }
+        self.limits.truncate(&mut ids);
         debug!(
             "Found ids {:?} for {}",
             ids,
@@ -251,7 +704,7 @@ impl<S: ItemsSource<AppLaunchItem> + 'static> AppItemSearchProvider<S> {
     /// It gets the previous search results and the current search terms as arguments, and should return an array of result IDs,
     /// just like GetInitialResulSet.
     fn get_subsearch_result_set(
-        &self,
+        &mut self,
         previous_results: Vec<&str>,
         terms: Vec<&str>,
     ) -> Vec<String> {
@@ -266,14 +719,27 @@ impl<S: ItemsSource<AppLaunchItem> + 'static> AppItemSearchProvider<S> {
             previous_results,
             self.app.id().unwrap()
         );
-        let candidates = previous_results
-            .iter()
-            .filter_map(|&id| self.items.get(id).map(|p| (id, p)));
-
-        let ids = find_matching_items(candidates, terms.as_slice())
-            .into_iter()
-            .map(|s| s.to_owned())
-            .collect();
+        let query = Query::parse(&terms);
+        // If previous results were cut to the maximum number, better matches for the refined
+        // terms may be missing from them, so search all items again.
+        let scored = if self.limits.is_exhausted(&previous_results) {
+            find_scored_items(
+                self.with_apps(self.items.iter().map(|(id, item)| (id.as_str(), item))),
+                self.match_mode,
+                &query,
+            )
+        } else {
+            let candidates = previous_results
+                .iter()
+                .filter_map(|&id| self.items.get(id).map(|p| (id, p)));
+            find_scored_items(self.with_apps(candidates), self.match_mode, &query)
+        };
+        let mut ids = self.rank_results(&terms, scored);
+        if let Some(id) = self.add_file_search_result(&terms) {
+            ids.retain(|existing| *existing != id);
+            ids.insert(0, id);
+        }
+        self.limits.truncate(&mut ids);
         debug!(
             "Found ids {:?} for {}",
             ids,
@@ -310,14 +776,24 @@ impl<S: ItemsSource<AppLaunchItem> + 'static> AppItemSearchProvider<S> {
             .filter_map(|id| {
                 self.items.get(id).map(|item| {
                     debug!("Compiling meta info for {}", id);
-                    let icon = IconExt::to_string(&self.app.icon().unwrap()).unwrap();
+                    let icon = match &item.icon {
+                        Some(file) => {
+                            IconExt::to_string(&gio::FileIcon::new(&gio::File::for_path(file)))
+                        }
+                        None => IconExt::to_string(&item.app(&self.app).icon().unwrap()),
+                    }
+                    .unwrap();
                     debug!("Using icon {} for id {}", icon, id);
+                    let description = self
+                        .source
+                        .describe_item(id)
+                        .unwrap_or_else(|| item.description().to_string());
 
                     let mut meta: HashMap<String, zvariant::Value> = HashMap::new();
                     meta.insert("id".to_string(), id.clone().into());
                     meta.insert("name".to_string(), (&item.name).into());
                     meta.insert("gicon".to_string(), icon.to_string().into());
-                    meta.insert("description".to_string(), item.target.description().into());
+                    meta.insert("description".to_string(), description.into());
                     meta
                 })
             })
@@ -332,39 +808,14 @@ impl<S: ItemsSource<AppLaunchItem> + 'static> AppItemSearchProvider<S> {
     /// This function is called when the user clicks on an individual result to open it in the application.
     /// The arguments are the result ID, the current search terms and a timestamp.
     ///
-    /// Launches the underlying app with the path to the selected item.
+    /// Launches the underlying app with the path to the selected item, and records the
+    /// activation in the history.
     fn activate_result(&self, id: &str, terms: Vec<&str>, timestamp: u32) -> zbus::fdo::Result<()> {
         trace!("Enter ActivateResult({}, {:?}, {})", id, terms, timestamp);
         debug!("Activating result {} for {:?} at {}", id, terms, timestamp);
         let result = if let Some(item) = self.items.get(id) {
-            info!(
-                "Launching recent item {:?} for {}",
-                item,
-                self.app.id().unwrap().as_str()
-            );
-            match &item.target {
-                AppLaunchTarget::File(path) => self.app.launch::<gio::AppLaunchContext>(
-                    &[gio::File::for_path(path)],
-                    Some(&self.launch_context),
-                ),
-                AppLaunchTarget::Uri(uri) => {
-                    self.app.launch_uris(&[uri], Some(&self.launch_context))
-                }
-            }
-            .map_err(|error| {
-                error!(
-                    "Failed to launch app {} for target {}: {}",
-                    self.app.id().unwrap(),
-                    item.target.description(),
-                    error,
-                );
-                zbus::fdo::Error::SpawnFailed(format!(
-                    "Failed to launch app {} for URI {}: {}",
-                    self.app.id().unwrap(),
-                    item.target.description(),
-                    error
-                ))
-            })
+            record_and_save_later(&self.history, &terms, id, SystemTime::now());
+            self.launch_item(item)
         } else {
             error!(
                 "Item with ID {} not found for {}",
@@ -388,25 +839,35 @@ impl<S: ItemsSource<AppLaunchItem> + 'static> AppItemSearchProvider<S> {
     /// This function is called when the user clicks on the provider icon to display more search results in the application.
     /// The arguments are the current search terms and a timestamp.
     ///
-    /// Currently it simply launches the app without any arguments.
+    /// Launches the matching item if the terms match exactly one item of the last search;
+    /// otherwise launches according to the configured fallback.
     fn launch_search(&self, terms: Vec<String>, timestamp: u32) -> zbus::fdo::Result<()> {
         trace!("Enter LaunchSearch({:?}, {:?})", terms, timestamp);
-        info!("Launching app {} directly", self.app.id().unwrap().as_str());
-        let result = self
-            .app
-            .launch(&[], Some(&self.launch_context))
-            .map_err(|error| {
-                error!(
-                    "Failed to launch app {}: {:#}",
-                    self.app.id().unwrap(),
-                    error
+        let action = launch_search_action(
+            self.with_apps(self.items.iter().map(|(id, item)| (id.as_str(), item))),
+            self.match_mode,
+            &Query::parse(&terms),
+            self.launch_search_fallback,
+        );
+        debug!(
+            "Launching {:?} for search {:?} of {} with fallback {:?}",
+            action,
+            terms,
+            self.app.id().unwrap(),
+            self.launch_search_fallback
+        );
+        let result = match action {
+            LaunchSearchAction::Item(id) => self.launch_item(&self.items[id]),
+            LaunchSearchAction::App => self.launch_app(),
+            LaunchSearchAction::Nothing => {
+                info!(
+                    "Not launching anything for search {:?} of {}",
+                    terms,
+                    self.app.id().unwrap()
                 );
-                zbus::fdo::Error::SpawnFailed(format!(
-                    "Failed to launch app {}: {}",
-                    self.app.id().unwrap(),
-                    error
-                ))
-            });
+                Ok(())
+            }
+        };
         trace!(
             "Enter LaunchSearch({:?}, {:?}) -> {:?}",
             terms,
@@ -419,25 +880,394 @@ impl<S: ItemsSource<AppLaunchItem> + 'static> AppItemSearchProvider<S> {
 
 #[cfg(test)]
 mod tests {
+    use crate::app::{AppLaunchItem, AppLaunchTarget};
+
+    /// An item with the given `name` and `path`, and no other attributes.
+    fn item(name: &str, path: &str) -> AppLaunchItem {
+        AppLaunchItem {
+            name: name.to_string(),
+            target: AppLaunchTarget::File(path.to_string()),
+            last_used: None,
+            keywords: vec![],
+            description: None,
+            icon: None,
+            app: None,
+        }
+    }
+
+    mod launch_search {
+        use std::cell::Cell;
+        use std::path::Path;
+        use std::rc::Rc;
+
+        use gio::prelude::*;
+        use indexmap::IndexMap;
+        use pretty_assertions::assert_eq;
+
+        use crate::app::*;
+
+        pub(super) fn item(name: &str) -> AppLaunchItem {
+            super::item(name, &format!("/home/foo/dev/{}", name))
+        }
+
+        fn items() -> IdMap<AppLaunchItem> {
+            vec!["mdcat", "mdless", "foo"]
+                .into_iter()
+                .map(|name| (name.to_string(), item(name)))
+                .collect()
+        }
+
+        pub(super) struct NoItems;
+
+        impl ItemsSource<AppLaunchItem> for NoItems {
+            type Err = String;
+
+            fn find_recent_items(&self) -> Result<IdMap<AppLaunchItem>, Self::Err> {
+                Ok(IndexMap::new())
+            }
+        }
+
+        /// Get a fake app which runs `true`.
+        pub(super) fn fake_app() -> gio::DesktopAppInfo {
+            let file = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("src")
+                .join("tests")
+                .join("applications")
+                .join("gnome-search-provider-common-fake.desktop");
+            gio::DesktopAppInfo::from_filename(file).unwrap()
+        }
+
+        /// Launch a search for `terms` in `items()` with `fallback`.
+        ///
+        /// Return how often the fake app was launched.
+        fn launch(terms: &[&str], fallback: LaunchSearchFallback) -> usize {
+            let launch_context = gio::AppLaunchContext::new();
+            let launched = Rc::new(Cell::new(0));
+            let counter = launched.clone();
+            launch_context.connect_launched(move |_, app, _| {
+                assert_eq!(
+                    app.id().unwrap(),
+                    "gnome-search-provider-common-fake.desktop"
+                );
+                counter.set(counter.get() + 1);
+            });
+            let mut provider = AppItemSearchProvider::new(
+                fake_app(),
+                NoItems,
+                launch_context,
+                MatchMode::Substring,
+                fallback,
+                ResultLimits::default(),
+                Rc::new(RefCell::new(History::default())),
+            );
+            provider.items = items();
+            provider
+                .launch_search(terms.iter().map(|s| s.to_string()).collect(), 0)
+                .unwrap();
+            launched.get()
+        }
+
+        #[test]
+        fn launch_single_match() {
+            assert_eq!(
+                launch_search_action(
+                    items().iter().map(|(id, item)| (id.as_str(), item)),
+                    MatchMode::Substring,
+                    &Query::parse(&["mdc"]),
+                    LaunchSearchFallback::Nothing
+                ),
+                LaunchSearchAction::Item("mdcat")
+            );
+        }
+
+        #[test]
+        fn launch_fallback_for_ambiguous_matches() {
+            let items = items();
+            let action = |fallback| {
+                launch_search_action(
+                    items.iter().map(|(id, item)| (id.as_str(), item)),
+                    MatchMode::Substring,
+                    &Query::parse(&["md"]),
+                    fallback,
+                )
+            };
+            assert_eq!(action(LaunchSearchFallback::App), LaunchSearchAction::App);
+            assert_eq!(
+                action(LaunchSearchFallback::Nothing),
+                LaunchSearchAction::Nothing
+            );
+            assert!(matches!(
+                action(LaunchSearchFallback::BestMatch),
+                LaunchSearchAction::Item(_)
+            ));
+        }
+
+        #[test]
+        fn launch_fallback_without_matches() {
+            let items = items();
+            let action = |fallback| {
+                launch_search_action(
+                    items.iter().map(|(id, item)| (id.as_str(), item)),
+                    MatchMode::Substring,
+                    &Query::parse(&["bar"]),
+                    fallback,
+                )
+            };
+            assert_eq!(action(LaunchSearchFallback::App), LaunchSearchAction::App);
+            assert_eq!(
+                action(LaunchSearchFallback::BestMatch),
+                LaunchSearchAction::App
+            );
+            assert_eq!(
+                action(LaunchSearchFallback::Nothing),
+                LaunchSearchAction::Nothing
+            );
+        }
+
+        #[test]
+        fn launch_search_launches_app() {
+            assert_eq!(launch(&["mdcat"], LaunchSearchFallback::Nothing), 1);
+            assert_eq!(launch(&["md"], LaunchSearchFallback::App), 1);
+            assert_eq!(launch(&["md"], LaunchSearchFallback::Nothing), 0);
+        }
+    }
+
+    mod result_set {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        use pretty_assertions::assert_eq;
+
+        use super::launch_search::{fake_app, item};
+        use crate::app::*;
+
+        /// A source of many recent items, named `project-0`, `project-1`, etc.
+        struct ManyItems(usize);
+
+        impl ItemsSource<AppLaunchItem> for ManyItems {
+            type Err = String;
+
+            fn find_recent_items(&self) -> Result<IdMap<AppLaunchItem>, Self::Err> {
+                Ok((0..self.0)
+                    .map(|i| (format!("project-{}", i), item(&format!("project-{}", i))))
+                    .collect())
+            }
+        }
+
+        fn provider(limits: ResultLimits) -> AppItemSearchProvider<ManyItems> {
+            AppItemSearchProvider::new(
+                fake_app(),
+                ManyItems(500),
+                gio::AppLaunchContext::new(),
+                MatchMode::Substring,
+                LaunchSearchFallback::App,
+                limits,
+                Rc::new(RefCell::new(History::default())),
+            )
+        }
+
+        #[test]
+        fn limit_results_of_large_item_sets() {
+            let mut provider = provider(ResultLimits {
+                max_results: Some(20),
+                min_score: 0.0,
+            });
+            let initial = provider.get_initial_result_set(vec!["project"]).unwrap();
+            assert_eq!(initial.len(), 20);
+
+            // The previous results are cut, so the subsearch must look at all items
+            let previous = initial.iter().map(String::as_str).collect();
+            let mut refined = provider.get_subsearch_result_set(previous, vec!["project-49"]);
+            refined.sort_unstable();
+            let mut expected = vec!["project-49".to_string()];
+            expected.extend((490..500).map(|i| format!("project-{}", i)));
+            assert_eq!(refined, expected);
+        }
+
+        #[test]
+        fn return_all_results_without_limit() {
+            let mut provider = provider(ResultLimits::default());
+            let initial = provider.get_initial_result_set(vec!["project"]).unwrap();
+            assert_eq!(initial.len(), 500);
+        }
+
+        #[test]
+        fn discard_results_below_min_score() {
+            let mut provider = provider(ResultLimits {
+                max_results: None,
+                min_score: 100.0,
+            });
+            let initial = provider.get_initial_result_set(vec!["project"]).unwrap();
+            assert_eq!(initial, Vec::<String>::new());
+        }
+    }
+
+    mod file_search {
+        use std::rc::Rc;
+
+        use pretty_assertions::assert_eq;
+
+        use super::item;
+        use super::launch_search::{fake_app, NoItems};
+        use crate::app::*;
+
+        #[test]
+        fn parse_file_search() {
+            assert_eq!(
+                FileSearch::parse(&["mdcat:src/main.rs:42"]),
+                Some(FileSearch {
+                    item: "mdcat",
+                    path: "src/main.rs",
+                    line: Some(42)
+                })
+            );
+            assert_eq!(
+                FileSearch::parse(&["mdcat:src/main.rs"]),
+                Some(FileSearch {
+                    item: "mdcat",
+                    path: "src/main.rs",
+                    line: None
+                })
+            );
+            for terms in &[
+                vec!["mdcat"],
+                vec!["mdcat:"],
+                vec![":src/main.rs"],
+                vec!["mdcat:/etc/passwd"],
+                vec!["mdcat:src/main.rs:foo"],
+                vec!["mdcat:src/main.rs", "foo"],
+                vec!["name:mdcat"],
+                vec!["path:src/main.rs"],
+                vec!["ide:rider:42"],
+                vec!["-mdcat:src/main.rs"],
+                vec!["mdcat:../../etc/passwd"],
+                vec!["mdcat:src/../../secret"],
+            ] {
+                assert_eq!(FileSearch::parse(terms), None, "{:?}", terms);
+            }
+        }
+
+        #[test]
+        fn commandline_at_line_replaces_field_code() {
+            assert_eq!(
+                commandline_at_line("/opt/idea/bin/idea.sh %u", "/home/foo/dev/main.rs", 42)
+                    .unwrap(),
+                "'/opt/idea/bin/idea.sh' --line 42 '/home/foo/dev/main.rs'"
+            );
+            assert_eq!(
+                commandline_at_line("idea", "/home/foo/dev/my main.rs", 42).unwrap(),
+                "'idea' --line 42 '/home/foo/dev/my main.rs'"
+            );
+            assert_eq!(
+                commandline_at_line("idea %f", "/home/foo/100%.rs", 1).unwrap(),
+                "'idea' --line 1 '/home/foo/100%%.rs'"
+            );
+        }
+
+        #[test]
+        fn commandline_at_line_keeps_quoted_arguments() {
+            assert_eq!(
+                commandline_at_line(
+                    r#""/opt/my idea/bin/idea.sh"  --title "100%% \"done\"" %U"#,
+                    "/home/foo/dev/main.rs",
+                    42
+                )
+                .unwrap(),
+                r#"'/opt/my idea/bin/idea.sh' '--title' '100%% "done"' --line 42 '/home/foo/dev/main.rs'"#
+            );
+            assert!(commandline_at_line("idea \"%f", "/home/foo/dev/main.rs", 42).is_err());
+        }
+
+        #[test]
+        fn commandline_at_line_with_flatpak_file_forwarding() {
+            assert_eq!(
+                commandline_at_line(
+                    "/usr/bin/flatpak run --command=idea --file-forwarding com.jetbrains.IntelliJ-IDEA-Ultimate @@ %f @@",
+                    "/home/foo/dev/main.rs",
+                    42
+                )
+                .unwrap(),
+                "'/usr/bin/flatpak' 'run' '--command=idea' '--file-forwarding' 'com.jetbrains.IntelliJ-IDEA-Ultimate' --line 42 '@@' '/home/foo/dev/main.rs' '@@'"
+            );
+        }
+
+        #[test]
+        fn add_file_search_results() {
+            let dir = env!("CARGO_MANIFEST_DIR");
+            let mut provider = AppItemSearchProvider::new(
+                fake_app(),
+                NoItems,
+                gio::AppLaunchContext::new(),
+                MatchMode::Substring,
+                LaunchSearchFallback::App,
+                ResultLimits::default(),
+                Rc::new(RefCell::new(History::default())),
+            );
+            provider.items = vec![
+                ("crate", item("crate", dir)),
+                ("solution", item("solution", &format!("{}/Cargo.toml", dir))),
+            ]
+            .into_iter()
+            .map(|(id, item)| (id.to_string(), item))
+            .collect();
+
+            let id = provider.add_file_search_result(&["crate:src/app.rs:42"]);
+            assert_eq!(id.as_deref(), Some("crate:src/app.rs:42"));
+            assert_eq!(
+                provider.items["crate:src/app.rs:42"].target,
+                AppLaunchTarget::ItemFile(format!("{}/src/app.rs", dir), Some(42))
+            );
+            // Look for files next to an item which is a file, e.g. a Rider solution
+            let id = provider.add_file_search_result(&["solution:src/lib.rs"]);
+            assert_eq!(id.as_deref(), Some("solution:src/lib.rs"));
+            assert_eq!(
+                provider.items["solution:src/lib.rs"].target,
+                AppLaunchTarget::ItemFile(format!("{}/src/lib.rs", dir), None)
+            );
+            // Do not search files in results of previous file searches
+            assert_eq!(
+                provider.add_file_search_result(&["lib.rs:Cargo.toml"]),
+                None
+            );
+            assert_eq!(
+                provider.add_file_search_result(&["crate:../Cargo.toml"]),
+                None
+            );
+            assert_eq!(
+                provider.add_file_search_result(&["crate:src/nothing.rs"]),
+                None
+            );
+        }
+    }
+
     mod search {
+        use std::time::{Duration, SystemTime};
+
         use pretty_assertions::assert_eq;
 
-        use crate::app::{AppLaunchItem, AppLaunchTarget};
-        use crate::matching::find_matching_items;
+        use super::item;
+        use crate::app::AppLaunchItem;
+        use crate::matching::{find_matching_items, MatchMode, Query};
 
         fn do_match<'a>(items: &[(&'a str, AppLaunchItem)], terms: &[&str]) -> Vec<&'a str> {
-            find_matching_items(items.iter().map(|(s, p)| (*s, p)), terms)
+            find_matching_items(
+                items.iter().map(|(s, p)| (*s, p)),
+                MatchMode::Substring,
+                &Query::parse(terms),
+            )
+        }
+
+        fn do_fuzzy_match<'a>(items: &[(&'a str, AppLaunchItem)], terms: &[&str]) -> Vec<&'a str> {
+            find_matching_items(
+                items.iter().map(|(s, p)| (*s, p)),
+                MatchMode::Fuzzy,
+                &Query::parse(terms),
+            )
         }
 
         #[test]
         fn matches_something() {
-            let items = vec![(
-                "foo",
-                AppLaunchItem {
-                    name: "mdcat".to_string(),
-                    target: AppLaunchTarget::File("/home/foo/dev/mdcat".to_string()),
-                },
-            )];
+            let items = vec![("foo", item("mdcat", "/home/foo/dev/mdcat"))];
             assert_eq!(do_match(&items, &["mdcat"]), ["foo"]);
         }
 
@@ -447,30 +1277,18 @@ mod tests {
             let items = vec![
                 (
                     "foo-1",
-                    AppLaunchItem {
-                        name: "ui-pattern-library".to_string(),
-                        target: AppLaunchTarget::File(
-                            "/home/foo/dev/something/ui-pattern-library".to_string(),
-                        ),
-                    },
+                    item(
+                        "ui-pattern-library",
+                        "/home/foo/dev/something/ui-pattern-library",
+                    ),
                 ),
                 (
                     "foo-2",
-                    AppLaunchItem {
-                        name: "dauntless-builder".to_string(),
-                        target: AppLaunchTarget::File(
-                            "/home/foo/dev/dauntless-builder".to_string(),
-                        ),
-                    },
+                    item("dauntless-builder", "/home/foo/dev/dauntless-builder"),
                 ),
                 (
                     "foo-3",
-                    AppLaunchItem {
-                        name: "typo3-ssr".to_string(),
-                        target: AppLaunchTarget::File(
-                            "/home/foo/dev/something/typo3-ssr".to_string(),
-                        ),
-                    },
+                    item("typo3-ssr", "/home/foo/dev/something/typo3-ssr"),
                 ),
             ];
             assert!(do_match(&items, &["flutter_test_app"]).is_empty());
@@ -478,70 +1296,195 @@ mod tests {
 
         #[test]
         fn ignore_case_of_name() {
-            let items = vec![(
-                "foo",
-                AppLaunchItem {
-                    name: "mdCat".to_string(),
-                    target: AppLaunchTarget::File("/home/foo/dev/foo".to_string()),
-                },
-            )];
+            let items = vec![("foo", item("mdCat", "/home/foo/dev/foo"))];
             assert_eq!(do_match(&items, &["Mdcat"]), ["foo"]);
         }
 
         #[test]
         fn ignore_case_of_path() {
-            let items = vec![(
-                "foo",
-                AppLaunchItem {
-                    name: "bar".to_string(),
-                    target: AppLaunchTarget::File("/home/foo/dev/mdcaT".to_string()),
-                },
-            )];
+            let items = vec![("foo", item("bar", "/home/foo/dev/mdcaT"))];
             assert_eq!(do_match(&items, &["Mdcat"]), ["foo"]);
         }
 
         #[test]
-        fn matches_in_name_rank_higher() {
+        fn matches_keywords() {
+            let items = vec![
+                (
+                    "1",
+                    AppLaunchItem {
+                        keywords: vec!["Work".to_string()],
+                        ..item("mdcat", "/home/foo/dev/mdcat")
+                    },
+                ),
+                (
+                    "2",
+                    AppLaunchItem {
+                        keywords: vec!["client-x".to_string()],
+                        ..item("bar", "/home/foo/dev/bar")
+                    },
+                ),
+            ];
+            assert_eq!(do_match(&items, &["work"]), ["1"]);
+        }
+
+        #[test]
+        fn terms_match_different_fields() {
             let items = vec![
                 (
                     "1",
                     AppLaunchItem {
-                        name: "bar".to_string(),
-                        // This matches foo as well because of /home/foo
-                        target: AppLaunchTarget::File("/home/foo/dev/bar".to_string()),
+                        keywords: vec!["work".to_string()],
+                        ..item("mdcat", "/home/foo/dev/mdcat")
                     },
                 ),
                 (
                     "2",
                     AppLaunchItem {
-                        name: "foo".to_string(),
-                        target: AppLaunchTarget::File("/home/foo/dev/foo".to_string()),
+                        keywords: vec!["private".to_string()],
+                        ..item("mdcat", "/home/foo/fork/mdcat")
                     },
                 ),
             ];
+            assert_eq!(do_match(&items, &["work", "mdcat"]), ["1"]);
+            assert_eq!(do_fuzzy_match(&items, &["work", "mdcat"]), ["1"]);
+        }
+
+        #[test]
+        fn matches_in_name_rank_higher() {
+            let items = vec![
+                (
+                    "1",
+                    // This matches foo as well because of /home/foo
+                    item("bar", "/home/foo/dev/bar"),
+                ),
+                ("2", item("foo", "/home/foo/dev/foo")),
+            ];
             assert_eq!(do_match(&items, &["foo"]), ["2", "1"]);
         }
 
         #[test]
         fn matches_at_end_of_path_rank_higher() {
+            let items = vec![
+                (
+                    "1",
+                    // This matches foo as well because of /home/foo
+                    item("p1", "/home/foo/dev/bar"),
+                ),
+                ("2", item("p1", "/home/foo/dev/foo")),
+            ];
+            assert_eq!(do_match(&items, &["foo"]), ["2", "1"]);
+        }
+
+        #[test]
+        fn fuzzy_matches_subsequences() {
+            let items = vec![
+                (
+                    "1",
+                    item(
+                        "gnome-search-providers-jetbrains",
+                        "/home/foo/dev/gnome-search-providers-jetbrains",
+                    ),
+                ),
+                ("2", item("mdcat", "/home/foo/dev/mdcat")),
+            ];
+            assert_eq!(do_fuzzy_match(&items, &["gspj"]), ["1"]);
+            assert_eq!(do_fuzzy_match(&items, &["gnome-srch"]), ["1"]);
+            assert!(do_match(&items, &["gspj"]).is_empty());
+        }
+
+        #[test]
+        fn fuzzy_matches_in_name_rank_higher() {
+            let items = vec![
+                (
+                    "1",
+                    // This matches foo as well because of /home/foo
+                    item("bar", "/home/foo/dev/bar"),
+                ),
+                ("2", item("format-tool", "/home/bar/dev/format-tool")),
+            ];
+            assert_eq!(do_fuzzy_match(&items, &["foo"]), ["2", "1"]);
+        }
+
+        #[test]
+        fn fuzzy_matches_at_word_boundaries_rank_higher() {
+            let items = vec![
+                ("1", item("gauss", "/home/foo/dev/gauss")),
+                ("2", item("gnome-search", "/home/foo/dev/gnome-search")),
+            ];
+            assert_eq!(do_fuzzy_match(&items, &["gs"]), ["2", "1"]);
+        }
+
+        #[test]
+        fn recently_used_items_rank_higher() {
+            let now = SystemTime::now();
             let items = vec![
                 (
                     "1",
                     AppLaunchItem {
-                        name: "p1".to_string(),
-                        // This matches foo as well because of /home/foo
-                        target: AppLaunchTarget::File("/home/foo/dev/bar".to_string()),
+                        last_used: Some(now - Duration::from_secs(365 * 24 * 60 * 60)),
+                        ..item("foo-old", "/home/foo/dev/foo-old")
                     },
                 ),
                 (
                     "2",
                     AppLaunchItem {
-                        name: "p1".to_string(),
-                        target: AppLaunchTarget::File("/home/foo/dev/foo".to_string()),
+                        last_used: Some(now - Duration::from_secs(60 * 60)),
+                        ..item("foo-new", "/home/foo/dev/foo-new")
                     },
                 ),
             ];
             assert_eq!(do_match(&items, &["foo"]), ["2", "1"]);
         }
+
+        #[test]
+        fn matches_in_name_rank_higher_than_recent_use() {
+            let items = vec![
+                (
+                    "1",
+                    AppLaunchItem {
+                        last_used: Some(SystemTime::now()),
+                        ..item("bar", "/home/foo/dev/bar")
+                    },
+                ),
+                ("2", item("foo", "/home/foo/dev/foo")),
+            ];
+            assert_eq!(do_match(&items, &["foo"]), ["2", "1"]);
+        }
+
+        #[test]
+        fn recently_used_items_rank_higher_for_filters_only() {
+            let now = SystemTime::now();
+            let items = vec![
+                (
+                    "1",
+                    AppLaunchItem {
+                        last_used: Some(now - Duration::from_secs(365 * 24 * 60 * 60)),
+                        ..item("foo-old", "/home/foo/dev/foo-old")
+                    },
+                ),
+                ("2", item("foo-unknown", "/home/foo/dev/foo-unknown")),
+                (
+                    "3",
+                    AppLaunchItem {
+                        last_used: Some(now - Duration::from_secs(60 * 60)),
+                        ..item("foo-new", "/home/foo/dev/foo-new")
+                    },
+                ),
+            ];
+            assert_eq!(do_match(&items, &["path:dev"]), ["3", "1", "2"]);
+            assert_eq!(do_match(&items, &[]), ["3", "1", "2"]);
+        }
+
+        #[test]
+        fn recent_use_does_not_match() {
+            let items = vec![(
+                "foo",
+                AppLaunchItem {
+                    last_used: Some(SystemTime::now()),
+                    ..item("mdcat", "/home/foo/dev/mdcat")
+                },
+            )];
+            assert!(do_match(&items, &["bar"]).is_empty());
+        }
     }
 }
@@ -0,0 +1,371 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A history of activated search results.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+/// The boost of a result which was activated just now.
+const ACTIVATION_BOOST: f64 = 10.0;
+
+/// The maximum boost of a result.
+const MAX_BOOST: f64 = 50.0;
+
+/// The time after which the boost of an activation halves.
+const ACTIVATION_HALF_LIFE: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// The maximum number of activations to remember.
+const MAX_ENTRIES: usize = 1000;
+
+/// How long to wait after an activation before saving the history.
+///
+/// Save all activations within this time at once.
+const SAVE_DELAY: Duration = Duration::from_secs(5);
+
+/// How often and when a result was activated for a query.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Activations {
+    /// How often the result was activated, with every activation decayed to its age at `last`.
+    score: f64,
+    /// When the result was activated last, in seconds since the epoch.
+    last: u64,
+}
+
+impl Activations {
+    /// Get the decayed score of these activations at `now`, in seconds since the epoch.
+    fn score_at(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last) as f64;
+        self.score * 0.5_f64.powf(age / ACTIVATION_HALF_LIFE.as_secs_f64())
+    }
+}
+
+/// Activated search results by query, to rank results which were activated before higher.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct History {
+    /// Where to save the history, or `None` to not save it.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Whether activations were recorded since the history was saved last.
+    #[serde(skip)]
+    unsaved: bool,
+    /// The activations of results by query and result ID.
+    queries: HashMap<String, HashMap<String, Activations>>,
+}
+
+/// Get the history key for search `terms`.
+fn query_key<S: AsRef<str>>(terms: &[S]) -> String {
+    terms
+        .iter()
+        .map(|term| term.as_ref().trim().to_lowercase())
+        .filter(|term| !term.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Seconds since the epoch at `time`.
+fn timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl History {
+    /// Load the history from `path`, and save the history to `path`.
+    ///
+    /// Start with an empty history if `path` doesn't exist or fails to load.
+    pub fn load(path: PathBuf) -> Self {
+        let history = match std::fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|error| {
+                warn!("Ignoring invalid history at {}: {}", path.display(), error);
+                History::default()
+            }),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => History::default(),
+            Err(error) => {
+                warn!("Failed to read history from {}: {}", path.display(), error);
+                History::default()
+            }
+        };
+        Self {
+            path: Some(path),
+            ..history
+        }
+    }
+
+    /// Save this history to its path, if it has one.
+    pub fn save(&mut self) {
+        self.unsaved = false;
+        if let Some(path) = &self.path {
+            match self.save_to(path) {
+                Ok(()) => debug!("Saved history to {}", path.display()),
+                Err(error) => warn!("Failed to save history to {}: {}", path.display(), error),
+            }
+        }
+    }
+
+    /// Save this history if it has activations which were not saved yet.
+    ///
+    /// Call this before exiting, to not lose activations whose delayed save is still pending.
+    pub fn flush(&mut self) {
+        if self.unsaved {
+            self.save();
+        }
+    }
+
+    /// Save this history to `path`.
+    fn save_to(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        // Write to a temporary file first, to not leave a truncated history behind.
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, serde_json::to_vec(self)?)?;
+        std::fs::rename(&temp, path)
+    }
+
+    /// Record that the result with the given `id` was activated at `time` for a search for `terms`.
+    ///
+    /// Forget the oldest activations if the history grows too large.  Do not save the history;
+    /// see [`record_and_save_later`].
+    pub fn record<S: AsRef<str>>(&mut self, terms: &[S], id: &str, time: SystemTime) {
+        let key = query_key(terms);
+        if key.is_empty() {
+            return;
+        }
+        let activations = self
+            .queries
+            .entry(key)
+            .or_default()
+            .entry(id.to_string())
+            .or_insert(Activations {
+                score: 0.0,
+                last: 0,
+            });
+        let now = timestamp(time);
+        activations.score = activations.score_at(now) + 1.0;
+        activations.last = now;
+        self.unsaved = true;
+        self.prune();
+    }
+
+    /// Forget the oldest activations beyond the maximum number of entries.
+    fn prune(&mut self) {
+        let mut entries: Vec<(u64, String, String)> = self
+            .queries
+            .iter()
+            .flat_map(|(key, ids)| {
+                ids.iter()
+                    .map(move |(id, activations)| (activations.last, key.clone(), id.clone()))
+            })
+            .collect();
+        if entries.len() <= MAX_ENTRIES {
+            return;
+        }
+        entries.sort_unstable();
+        for (_, key, id) in &entries[..entries.len() - MAX_ENTRIES] {
+            if let Some(ids) = self.queries.get_mut(key) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.queries.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Get the activated results of all queries which start with the query for `terms`.
+    fn matching_queries<S: AsRef<str>>(&self, terms: &[S]) -> Vec<&HashMap<String, Activations>> {
+        let key = query_key(terms);
+        if key.is_empty() {
+            return Vec::new();
+        }
+        self.queries
+            .iter()
+            .filter(|(query, _)| query.starts_with(&key))
+            .map(|(_, ids)| ids)
+            .collect()
+    }
+
+    /// Get the boost of the result with the given `id` among the activated results of
+    /// `queries` at `now`, in seconds since the epoch.
+    fn boost_in(queries: &[&HashMap<String, Activations>], id: &str, now: u64) -> f64 {
+        let boost: f64 = queries
+            .iter()
+            .filter_map(|ids| ids.get(id))
+            .map(|activations| ACTIVATION_BOOST * activations.score_at(now))
+            .sum();
+        boost.min(MAX_BOOST)
+    }
+
+    /// Get the boost of the result with the given `id` for a search for `terms` at `now`.
+    ///
+    /// Every activation of the result for a query which starts with `terms` boosts the
+    /// result; the boost of an activation decays with its age.
+    pub fn boost<S: AsRef<str>>(&self, terms: &[S], id: &str, now: SystemTime) -> f64 {
+        Self::boost_in(&self.matching_queries(terms), id, timestamp(now))
+    }
+
+    /// Boost `scored` results for a search for `terms` at `now`.
+    ///
+    /// Add the boost of every result to its score, and return all results ordered by boosted
+    /// score in descending order.
+    pub fn boost_all<S, K>(
+        &self,
+        terms: &[S],
+        scored: Vec<(f64, K)>,
+        now: SystemTime,
+    ) -> Vec<(f64, K)>
+    where
+        S: AsRef<str>,
+        K: AsRef<str>,
+    {
+        let queries = self.matching_queries(terms);
+        let now = timestamp(now);
+        let mut boosted: Vec<(f64, K)> = scored
+            .into_iter()
+            .map(|(score, id)| (score + Self::boost_in(&queries, id.as_ref(), now), id))
+            .collect();
+        boosted.sort_by(|(score_a, _), (score_b, _)| score_b.partial_cmp(score_a).unwrap());
+        boosted
+    }
+
+    /// Rank `scored` results for a search for `terms` at `now`.
+    ///
+    /// Return the IDs of all results ordered by boosted score in descending order, see
+    /// [`History::boost_all`].
+    pub fn rank<S, K>(&self, terms: &[S], scored: Vec<(f64, K)>, now: SystemTime) -> Vec<K>
+    where
+        S: AsRef<str>,
+        K: AsRef<str>,
+    {
+        self.boost_all(terms, scored, now)
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect()
+    }
+}
+
+/// Record an activation in `history` with [`History::record`], and save `history` a few
+/// seconds later on the thread-default main loop.
+///
+/// Save all activations within these seconds at once, to not write the history while
+/// handling every activation.
+pub fn record_and_save_later<S: AsRef<str>>(
+    history: &Rc<RefCell<History>>,
+    terms: &[S],
+    id: &str,
+    time: SystemTime,
+) {
+    let mut recorded = history.borrow_mut();
+    let save_pending = recorded.unsaved;
+    recorded.record(terms, id, time);
+    if recorded.path.is_some() && recorded.unsaved && !save_pending {
+        let history = history.clone();
+        glib::MainContext::ref_thread_default().spawn_local(async move {
+            glib::timeout_future(SAVE_DELAY).await;
+            history.borrow_mut().flush();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn now() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    #[test]
+    fn boost_activated_results_for_query_prefixes() {
+        let mut history = History::default();
+        history.record(&["API"], "api-server", now());
+        history.record(&["api"], "api-server", now());
+        assert_eq!(history.boost(&["api"], "api-server", now()), 20.0);
+        assert_eq!(history.boost(&["ap"], "api-server", now()), 20.0);
+        assert_eq!(history.boost(&["api-client"], "api-server", now()), 0.0);
+        assert_eq!(history.boost(&["api"], "api-client", now()), 0.0);
+        assert_eq!(history.boost(&[""], "api-server", now()), 0.0);
+    }
+
+    #[test]
+    fn boost_decays_with_age() {
+        let mut history = History::default();
+        history.record(&["api"], "api-server", now());
+        assert_eq!(history.boost(&["api"], "api-server", now() + 14 * DAY), 5.0);
+    }
+
+    #[test]
+    fn boost_is_limited() {
+        let mut history = History::default();
+        for _ in 0..10 {
+            history.record(&["api"], "api-server", now());
+        }
+        assert_eq!(history.boost(&["api"], "api-server", now()), MAX_BOOST);
+    }
+
+    #[test]
+    fn old_activations_decay_before_a_new_activation() {
+        let mut history = History::default();
+        for _ in 0..10 {
+            history.record(&["api"], "api-server", now());
+        }
+        let later = now() + 140 * DAY;
+        history.record(&["api"], "api-server", later);
+        let boost = history.boost(&["api"], "api-server", later);
+        assert!(boost < MAX_BOOST, "{}", boost);
+        assert!((boost - 10.1).abs() < 0.01, "{}", boost);
+    }
+
+    #[test]
+    fn rank_boosted_results_first() {
+        let mut history = History::default();
+        history.record(&["api"], "api-server", now());
+        let scored = vec![(12.0, "api-client"), (10.5, "api-server"), (8.0, "rapid")];
+        assert_eq!(
+            history.rank(&["api"], scored, now()),
+            vec!["api-server", "api-client", "rapid"]
+        );
+    }
+
+    #[test]
+    fn prune_oldest_activations() {
+        let mut history = History::default();
+        for i in 0..=MAX_ENTRIES as u64 {
+            history.record(&["api"], &i.to_string(), now() + Duration::from_secs(i));
+        }
+        assert_eq!(history.queries["api"].len(), MAX_ENTRIES);
+        assert!(!history.queries["api"].contains_key("0"));
+        assert!(history.queries["api"].contains_key("1"));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir()
+            .join(format!(
+                "gnome-search-provider-history-{}",
+                std::process::id()
+            ))
+            .join("history.json");
+        let mut history = History::load(path.clone());
+        assert_eq!(history.queries, HashMap::new());
+        history.record(&["api"], "api-server", now());
+        assert!(!path.exists());
+        history.flush();
+        let loaded = History::load(path.clone());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(loaded, history);
+    }
+}
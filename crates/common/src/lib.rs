@@ -10,6 +10,7 @@
 
 pub mod app;
 pub mod dbus;
+pub mod history;
 pub mod log;
 pub mod mainloop;
 pub mod matching;
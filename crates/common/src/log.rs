@@ -77,3 +77,12 @@ pub fn setup_logging_for_service(version: &'static str) {
         env_logger::Builder::from_env(env).init();
     }
 }
+
+/// Setup logging for command line tools.
+///
+/// Log to standard error, and set the maximum level to "warn", unless `$RUST_LOG` says otherwise.
+pub fn setup_logging_for_cli() {
+    glib::log_set_default_handler(glib::rust_log_handler);
+    let env = env_logger::Env::default().default_filter_or("warn");
+    env_logger::Builder::from_env(env).init();
+}
@@ -7,48 +7,355 @@
 //! Utilities for matching stuff.
 
 use std::fmt::{Debug, Display};
+use std::rc::Rc;
 
 use log::trace;
+use serde::Deserialize;
 
 pub use indexmap::IndexMap;
 
+/// How to match a term against a text.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// The term must occur in the text.
+    #[default]
+    Substring,
+    /// All characters of the term must occur in the text in the same order, but not necessarily
+    /// next to each other.
+    ///
+    /// Prefer matches at the beginning of words, after `-`, `_` or other separators, or at camelCase
+    /// humps, and consecutive matches.
+    Fuzzy,
+}
+
+/// A match of a term in a text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TermMatch {
+    /// The quality of the match, between 0 (exclusive) and 1 for a perfect match.
+    pub quality: f64,
+    /// The position of the match relative to the length of the text, between 0 and 1 (exclusive).
+    pub position: f64,
+}
+
+/// The score of a single character in a fuzzy match.
+const FUZZY_CHAR_SCORE: f64 = 1.0;
+
+/// The bonus for a fuzzy match at the beginning of a word or at a camelCase hump.
+const FUZZY_BOUNDARY_BONUS: f64 = 2.0;
+
+/// The bonus for a fuzzy match right after the previous match.
+const FUZZY_CONSECUTIVE_BONUS: f64 = 2.0;
+
+/// Whether `c` separates words.
+fn is_separator(c: char) -> bool {
+    matches!(c, '-' | '_' | '/' | '\\' | '.' | ' ')
+}
+
+/// Find the best fuzzy match of `term` in `text`, ignoring case.
+///
+/// Each matched character scores 1, plus a bonus of 2 if it's at the beginning of a word or a camelCase
+/// hump, or if it directly follows the previously matched character.  Among alignments with the same score
+/// prefer the right-most one.
+fn fuzzy_match(text: &str, term: &str) -> Option<TermMatch> {
+    let text: Vec<char> = text.chars().collect();
+    let lowercase_text: Vec<char> = text
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let term: Vec<char> = term
+        .chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect();
+    if term.is_empty() || text.is_empty() {
+        return None;
+    }
+    let bonus = |j: usize| {
+        let at_boundary = match j.checked_sub(1).map(|i| text[i]) {
+            None => true,
+            Some(previous) => {
+                is_separator(previous) || (previous.is_lowercase() && text[j].is_uppercase())
+            }
+        };
+        if at_boundary {
+            FUZZY_BOUNDARY_BONUS
+        } else {
+            0.0
+        }
+    };
+
+    // best[j] is the best score and the start of the corresponding alignment for the term so far,
+    // with the last term character matched at text position j.
+    let mut best: Vec<Option<(f64, usize)>> = lowercase_text
+        .iter()
+        .enumerate()
+        .map(|(j, c)| (*c == term[0]).then(|| (FUZZY_CHAR_SCORE + bonus(j), j)))
+        .collect();
+    for &t in &term[1..] {
+        let mut next = vec![None; text.len()];
+        // The best alignment ending before the previous text position.
+        let mut best_before: Option<(f64, usize)> = None;
+        for j in 1..text.len() {
+            if lowercase_text[j] == t {
+                let consecutive = best[j - 1]
+                    .map(|(score, start)| (score + FUZZY_CONSECUTIVE_BONUS.max(bonus(j)), start));
+                let gap = best_before.map(|(score, start)| (score + bonus(j), start));
+                next[j] = match (consecutive, gap) {
+                    (Some(a), Some(b)) => Some(if b.0 > a.0 { b } else { a }),
+                    (a, b) => a.or(b),
+                }
+                .map(|(score, start)| (score + FUZZY_CHAR_SCORE, start));
+            }
+            best_before = match (best_before, best[j - 1]) {
+                (Some(a), Some(b)) => Some(if a.0 > b.0 { a } else { b }),
+                (a, b) => a.or(b),
+            };
+        }
+        best = next;
+    }
+
+    let max_score = term.len() as f64 * (FUZZY_CHAR_SCORE + FUZZY_BOUNDARY_BONUS);
+    best.into_iter()
+        .flatten()
+        .fold(None, |acc: Option<(f64, usize)>, m| match acc {
+            Some(a) if a.0 > m.0 => Some(a),
+            _ => Some(m),
+        })
+        .map(|(score, start)| TermMatch {
+            quality: score / max_score,
+            position: start as f64 / text.len() as f64,
+        })
+}
+
+impl MatchMode {
+    /// Find `term` in `text`, ignoring case.
+    ///
+    /// Return `None` if `term` doesn't match `text`.
+    ///
+    /// In substring mode any occurrence of `term` is a perfect match, and the position is the
+    /// position of the right-most occurrence.
+    pub fn find(self, text: &str, term: &str) -> Option<TermMatch> {
+        match self {
+            MatchMode::Substring => {
+                let text = text.to_lowercase();
+                text.rfind(&term.to_lowercase()).map(|index| TermMatch {
+                    quality: 1.0,
+                    position: index as f64 / text.len() as f64,
+                })
+            }
+            MatchMode::Fuzzy => fuzzy_match(text, term),
+        }
+    }
+}
+
 /// Match against a list of terms and return a score.
 pub trait ScoreMatchable {
-    /// Match self against `terms` and return a score about how "well" self matches `terms`.
+    /// Match self against `terms` with the given `mode` and return a score about how "well" self matches `terms`.
     ///
     /// A score of 0 or less denotes that `self` doesn't match `terms`; a score greater than zero indicates
     /// a match.
     ///
     /// The higher the score the better self matches `terms`; as a rule of thumb a score of 100 should be
     /// considered a perfect match.
-    fn match_score<S: AsRef<str>>(&self, terms: &[S]) -> f64;
+    fn match_score<S: AsRef<str>>(&self, mode: MatchMode, terms: &[S]) -> f64;
+
+    /// Get the text of the given `field` of self, to match query terms restricted to `field`.
+    ///
+    /// Return `None` if self doesn't have `field`; terms restricted to `field` never match then.
+    fn field(&self, _field: QueryField) -> Option<String> {
+        None
+    }
+
+    /// Get a score for how recently self was used, to rank matches of queries without plain terms.
+    ///
+    /// Return 0 if it's not known when self was used.
+    fn recency_score(&self) -> f64 {
+        0.0
+    }
 }
 
 impl<'a, T> ScoreMatchable for &'a T
 where
     T: ScoreMatchable,
 {
-    fn match_score<S: AsRef<str>>(&self, terms: &[S]) -> f64 {
-        (*self).match_score(terms)
+    fn match_score<S: AsRef<str>>(&self, mode: MatchMode, terms: &[S]) -> f64 {
+        (*self).match_score(mode, terms)
+    }
+
+    fn field(&self, field: QueryField) -> Option<String> {
+        (*self).field(field)
+    }
+
+    fn recency_score(&self) -> f64 {
+        (*self).recency_score()
+    }
+}
+
+/// An item along with the app it belongs to, so that `ide:` terms can match the app.
+#[derive(Debug)]
+pub struct InApp<T> {
+    /// The item.
+    pub item: T,
+    /// The text to match `ide:` terms against, e.g. the name of the app.
+    pub app: String,
+}
+
+impl<T: ScoreMatchable> ScoreMatchable for InApp<T> {
+    fn match_score<S: AsRef<str>>(&self, mode: MatchMode, terms: &[S]) -> f64 {
+        self.item.match_score(mode, terms)
+    }
+
+    fn field(&self, field: QueryField) -> Option<String> {
+        match field {
+            QueryField::App => Some(self.app.clone()),
+            field => self.item.field(field),
+        }
+    }
+
+    fn recency_score(&self) -> f64 {
+        self.item.recency_score()
+    }
+}
+
+/// A field of an item which a query term can be restricted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryField {
+    /// The name of an item, for `name:` terms.
+    Name,
+    /// The path of an item, for `path:` terms.
+    Path,
+    /// The app an item belongs to, for `ide:` terms.
+    App,
+}
+
+/// A term of a query which filters items.
+#[derive(Debug, Clone, PartialEq)]
+struct QueryFilter {
+    /// The field to match the term against, or `None` to match the term like any other term.
+    field: Option<QueryField>,
+    /// The text of the term.
+    text: String,
+    /// Whether to exclude items which match the term.
+    negated: bool,
+}
+
+/// The score of an item which passes all filters of a query without plain terms.
+const FILTER_SCORE: f64 = 1.0;
+
+/// A search query.
+///
+/// A query consists of plain terms which score items, and of filters: a term prefixed with
+/// `-` excludes all items which match it, and a term prefixed with `name:`, `path:` or `ide:`
+/// only matches the name, the path or the app of an item respectively, e.g. `-test`,
+/// `name:mdcat`, `path:~/work` or `-ide:rider`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Query {
+    /// Plain terms to score items with.
+    terms: Vec<String>,
+    /// Terms which filter items.
+    filters: Vec<QueryFilter>,
+}
+
+impl Query {
+    /// Parse a query from search `terms`.
+    ///
+    /// Ignore blank terms, take terms with an unknown field prefix or without text after the
+    /// prefix literally, and expand `~` at the beginning of `path:` terms to the home directory.
+    pub fn parse<S: AsRef<str>>(terms: &[S]) -> Self {
+        let mut query = Query::default();
+        let terms = terms
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|term| !term.trim().is_empty());
+        for term in terms {
+            let (negated, rest) = match term.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (true, rest),
+                _ => (false, term),
+            };
+            let (field, text) = match rest.split_once(':') {
+                Some(("name", text)) if !text.is_empty() => (Some(QueryField::Name), text),
+                Some(("path", text)) if !text.is_empty() => (Some(QueryField::Path), text),
+                Some(("ide", text)) if !text.is_empty() => (Some(QueryField::App), text),
+                _ => (None, rest),
+            };
+            let text = match (field, text.strip_prefix('~')) {
+                (Some(QueryField::Path), Some(rest))
+                    if rest.is_empty() || rest.starts_with('/') =>
+                {
+                    format!("{}{}", glib::home_dir().display(), rest)
+                }
+                _ => text.to_string(),
+            };
+            if negated || field.is_some() {
+                query.filters.push(QueryFilter {
+                    field,
+                    text,
+                    negated,
+                });
+            } else {
+                query.terms.push(text);
+            }
+        }
+        query
+    }
+
+    /// The plain terms of this query.
+    pub fn terms(&self) -> &[String] {
+        &self.terms
+    }
+
+    /// Whether `filter` matches `item` with the given `mode`.
+    fn filter_matches<T: ScoreMatchable>(filter: &QueryFilter, item: &T, mode: MatchMode) -> bool {
+        match filter.field {
+            None => 0.0 < item.match_score(mode, &[&filter.text]),
+            Some(field) => item
+                .field(field)
+                .is_some_and(|text| mode.find(&text, &filter.text).is_some()),
+        }
+    }
+
+    /// Score `item` for this query with the given `mode`.
+    ///
+    /// Score the plain terms with [`ScoreMatchable::match_score`], or give a small fixed score
+    /// plus the [`ScoreMatchable::recency_score`] if the query has no plain terms, so that a
+    /// query with only filters matches all items which pass the filters, and an empty query
+    /// matches all items, recently used items first.  Return 0 if the item does not pass all
+    /// filters.
+    pub fn score<T: ScoreMatchable>(&self, item: &T, mode: MatchMode) -> f64 {
+        let score = if self.terms.is_empty() {
+            FILTER_SCORE + item.recency_score()
+        } else {
+            item.match_score(mode, &self.terms)
+        };
+        let passes = 0.0 < score
+            && self
+                .filters
+                .iter()
+                .all(|filter| filter.negated != Self::filter_matches(filter, item, mode));
+        if passes {
+            score
+        } else {
+            0.0
+        }
     }
 }
 
-/// Find all items from `items` which match the given `terms`.
+/// Score all items from `items` against the given `query` with the given `mode`.
 ///
 /// `items` is an iterator over pairs of `(id, item)`.
 ///
-/// For each item compute the score with `MatchScore`; discard projects with zero score,
-/// and return a list of item IDs with non-zero score, ordered by score in descending order.
-pub fn find_matching_items<'a, I, T, K, Item>(items: I, terms: &'a [T]) -> Vec<K>
+/// For each item compute the score with [`Query::score`]; discard projects with zero score,
+/// and return a list of pairs of score and ID for items with non-zero score, ordered by
+/// score in descending order.
+pub fn find_scored_items<I, K, Item>(items: I, mode: MatchMode, query: &Query) -> Vec<(f64, K)>
 where
-    I: Iterator<Item = (K, Item)> + 'a,
+    I: Iterator<Item = (K, Item)>,
     Item: ScoreMatchable,
-    T: AsRef<str>,
     K: Debug,
 {
     let mut matches: Vec<(f64, K)> = items
         .filter_map(move |(id, item)| {
-            let score = item.match_score(terms);
+            let score = query.score(&item, mode);
             if 0.0 < score {
                 Some((score, id))
             } else {
@@ -58,12 +365,64 @@ where
         .collect();
     // Sort by score, descending
     matches.sort_by(|(score_a, _), (score_b, _)| score_b.partial_cmp(score_a).unwrap());
-    trace!(
-        "Matches {:?} for terms {:?}",
-        matches,
-        terms.iter().map(|s| s.as_ref()).collect::<Vec<&str>>()
-    );
-    matches.into_iter().map(move |(_, id)| id).collect()
+    trace!("Matches {:?} for query {:?}", matches, query);
+    matches
+}
+
+/// Find all items from `items` which match the given `query` with the given `mode`.
+///
+/// `items` is an iterator over pairs of `(id, item)`.
+///
+/// Return a list of item IDs with non-zero score, ordered by score in descending order;
+/// see [`find_scored_items`].
+pub fn find_matching_items<I, K, Item>(items: I, mode: MatchMode, query: &Query) -> Vec<K>
+where
+    I: Iterator<Item = (K, Item)>,
+    Item: ScoreMatchable,
+    K: Debug,
+{
+    find_scored_items(items, mode, query)
+        .into_iter()
+        .map(|(_, id)| id)
+        .collect()
+}
+
+/// Limits for the results of a search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResultLimits {
+    /// The maximum number of results, or `None` to return all results.
+    pub max_results: Option<usize>,
+    /// The minimum score of a result.
+    pub min_score: f64,
+}
+
+impl Default for ResultLimits {
+    fn default() -> Self {
+        Self {
+            max_results: None,
+            min_score: 0.0,
+        }
+    }
+}
+
+impl ResultLimits {
+    /// Discard all `scored` results below the minimum score.
+    pub fn retain_min_score<K>(&self, scored: &mut Vec<(f64, K)>) {
+        scored.retain(|(score, _)| self.min_score <= *score);
+    }
+
+    /// Cut `results` to the maximum number of results.
+    pub fn truncate<K>(&self, results: &mut Vec<K>) {
+        if let Some(max_results) = self.max_results {
+            results.truncate(max_results);
+        }
+    }
+
+    /// Whether `results` may have been cut to the maximum number of results.
+    pub fn is_exhausted<K>(&self, results: &[K]) -> bool {
+        self.max_results
+            .is_some_and(|max_results| max_results <= results.len())
+    }
 }
 
 /// A map of IDs to items which can be matched.
@@ -76,4 +435,239 @@ pub trait ItemsSource<T: ScoreMatchable> {
 
     /// Find matchable items.
     fn find_recent_items(&self) -> Result<IdMap<T>, Self::Err>;
+
+    /// Describe the item with the given `id`.
+    ///
+    /// Only called for items shown to the user, to let sources describe items lazily.
+    ///
+    /// Return `None` to use the description of the item itself.
+    fn describe_item(&self, _id: &str) -> Option<String> {
+        None
+    }
+}
+
+/// A shared source of matchable items.
+impl<T: ScoreMatchable, S: ItemsSource<T>> ItemsSource<T> for Rc<S> {
+    type Err = S::Err;
+
+    fn find_recent_items(&self) -> Result<IdMap<T>, Self::Err> {
+        self.as_ref().find_recent_items()
+    }
+
+    fn describe_item(&self, id: &str) -> Option<String> {
+        self.as_ref().describe_item(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// An item which scores 1 for every term in its name.
+    struct Item {
+        name: &'static str,
+        path: &'static str,
+    }
+
+    impl ScoreMatchable for Item {
+        fn match_score<S: AsRef<str>>(&self, mode: MatchMode, terms: &[S]) -> f64 {
+            terms
+                .iter()
+                .try_fold(0.0, |score, term| {
+                    mode.find(self.name, term.as_ref()).map(|_| score + 1.0)
+                })
+                .unwrap_or_default()
+        }
+
+        fn field(&self, field: QueryField) -> Option<String> {
+            match field {
+                QueryField::Name => Some(self.name.to_string()),
+                QueryField::Path => Some(self.path.to_string()),
+                QueryField::App => None,
+            }
+        }
+    }
+
+    const MDCAT: Item = Item {
+        name: "mdcat",
+        path: "/home/foo/work/mdcat",
+    };
+
+    fn score(terms: &[&str], item: &Item) -> f64 {
+        Query::parse(terms).score(item, MatchMode::Substring)
+    }
+
+    #[test]
+    fn parse_query() {
+        let query = Query::parse(&["foo", "-bar", "name:baz", "-path:/srv", "ide:rider"]);
+        assert_eq!(query.terms(), ["foo"]);
+        assert_eq!(
+            query.filters,
+            vec![
+                QueryFilter {
+                    field: None,
+                    text: "bar".to_string(),
+                    negated: true
+                },
+                QueryFilter {
+                    field: Some(QueryField::Name),
+                    text: "baz".to_string(),
+                    negated: false
+                },
+                QueryFilter {
+                    field: Some(QueryField::Path),
+                    text: "/srv".to_string(),
+                    negated: true
+                },
+                QueryFilter {
+                    field: Some(QueryField::App),
+                    text: "rider".to_string(),
+                    negated: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_query_literal_terms() {
+        let query = Query::parse(&["-", "name:", "mdcat:src/main.rs", "foo-bar"]);
+        assert_eq!(
+            query.terms(),
+            ["-", "name:", "mdcat:src/main.rs", "foo-bar"]
+        );
+        assert!(query.filters.is_empty());
+    }
+
+    #[test]
+    fn parse_query_expands_home_in_paths() {
+        let home = glib::home_dir();
+        let query = Query::parse(&["path:~/work", "name:~/work"]);
+        assert_eq!(query.filters[0].text, format!("{}/work", home.display()));
+        assert_eq!(query.filters[1].text, "~/work");
+    }
+
+    #[test]
+    fn query_excludes_negated_terms() {
+        assert_eq!(score(&["md"], &MDCAT), 1.0);
+        assert_eq!(score(&["md", "-cat"], &MDCAT), 0.0);
+        assert_eq!(score(&["md", "-dog"], &MDCAT), 1.0);
+        assert_eq!(score(&["md", "-path:work"], &MDCAT), 0.0);
+        assert_eq!(score(&["md", "-ide:rider"], &MDCAT), 1.0);
+    }
+
+    #[test]
+    fn query_restricts_terms_to_fields() {
+        assert_eq!(score(&["name:cat"], &MDCAT), FILTER_SCORE);
+        assert_eq!(score(&["name:work"], &MDCAT), 0.0);
+        assert_eq!(score(&["md", "path:work"], &MDCAT), 1.0);
+        assert_eq!(score(&["md", "path:/srv"], &MDCAT), 0.0);
+        assert_eq!(score(&["ide:rider"], &MDCAT), 0.0);
+        let in_app = InApp {
+            item: MDCAT,
+            app: "Rider jetbrains-rider.desktop".to_string(),
+        };
+        assert_eq!(
+            Query::parse(&["ide:rider"]).score(&in_app, MatchMode::Substring),
+            FILTER_SCORE
+        );
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert_eq!(score(&[], &MDCAT), FILTER_SCORE);
+        assert_eq!(score(&["", " "], &MDCAT), FILTER_SCORE);
+        assert_eq!(
+            Query::parse(&[""]).score(&MDCAT, MatchMode::Fuzzy),
+            FILTER_SCORE
+        );
+    }
+
+    fn quality(mode: MatchMode, text: &str, term: &str) -> f64 {
+        mode.find(text, term).map_or(0.0, |m| m.quality)
+    }
+
+    #[test]
+    fn substring_finds_right_most_occurrence() {
+        assert_eq!(
+            MatchMode::Substring.find("/home/foo/dev/Foo", "foo"),
+            Some(TermMatch {
+                quality: 1.0,
+                position: 14.0 / 17.0
+            })
+        );
+        assert_eq!(MatchMode::Substring.find("/home/foo/dev/bar", "baz"), None);
+    }
+
+    #[test]
+    fn fuzzy_matches_subsequences() {
+        let text = "gnome-search-providers-jetbrains";
+        assert!(MatchMode::Fuzzy.find(text, "gspj").is_some());
+        assert!(MatchMode::Fuzzy.find(text, "gnome-srch").is_some());
+        assert!(MatchMode::Fuzzy.find(text, "GnomeSearch").is_some());
+        assert_eq!(MatchMode::Fuzzy.find(text, "jgs"), None);
+        assert_eq!(MatchMode::Fuzzy.find(text, "gnomex"), None);
+        assert_eq!(MatchMode::Fuzzy.find(text, ""), None);
+    }
+
+    #[test]
+    fn fuzzy_prefix_is_perfect() {
+        assert_eq!(quality(MatchMode::Fuzzy, "mdcat", "mdcat"), 1.0);
+        assert_eq!(
+            quality(MatchMode::Fuzzy, "gnome-search-providers-jetbrains", "gspj"),
+            1.0
+        );
+    }
+
+    #[test]
+    fn fuzzy_prefers_word_boundaries() {
+        assert!(
+            quality(MatchMode::Fuzzy, "gnome-search", "gs")
+                > quality(MatchMode::Fuzzy, "gauss", "gs")
+        );
+        assert!(
+            quality(MatchMode::Fuzzy, "gnome_search", "gs")
+                > quality(MatchMode::Fuzzy, "gauss", "gs")
+        );
+    }
+
+    #[test]
+    fn fuzzy_prefers_camel_case_humps() {
+        assert!(
+            quality(MatchMode::Fuzzy, "gnomeSearchProvider", "gsp")
+                > quality(MatchMode::Fuzzy, "gnomesearchprovider", "gsp")
+        );
+    }
+
+    #[test]
+    fn fuzzy_prefers_consecutive_matches() {
+        assert!(
+            quality(MatchMode::Fuzzy, "xsearchx", "search")
+                > quality(MatchMode::Fuzzy, "xsxexaxrxcxhx", "search")
+        );
+    }
+
+    #[test]
+    fn result_limits() {
+        let limits = ResultLimits {
+            max_results: Some(2),
+            min_score: 1.0,
+        };
+        let mut scored = vec![(3.0, "a"), (1.0, "b"), (0.5, "c")];
+        limits.retain_min_score(&mut scored);
+        assert_eq!(scored, vec![(3.0, "a"), (1.0, "b")]);
+        let mut results = vec!["a", "b", "c"];
+        assert!(limits.is_exhausted(&results));
+        limits.truncate(&mut results);
+        assert_eq!(results, vec!["a", "b"]);
+        assert!(!limits.is_exhausted(&results[..1]));
+        assert!(!ResultLimits::default().is_exhausted(&results));
+    }
+
+    #[test]
+    fn fuzzy_position_prefers_right_most_match() {
+        let m = MatchMode::Fuzzy.find("/home/foo/dev/foo", "foo").unwrap();
+        assert_eq!(m.position, 14.0 / 17.0);
+    }
 }
@@ -0,0 +1,749 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Configuration files of this service.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use gnome_search_provider_common::app::LaunchSearchFallback;
+use gnome_search_provider_common::matching::{MatchMode, ResultLimits};
+use log::{debug, error, info};
+use serde::Deserialize;
+
+use crate::description::DescriptionTemplate;
+use crate::missing::MissingProjects;
+use crate::{ConfigHome, ConfigLocation, ProviderDefinition, PROVIDERS};
+
+/// The name of the configuration file.
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// The directory for system-wide configuration.
+const SYSTEM_CONFIG_DIR: &str = "/etc/gnome-search-providers-jetbrains";
+
+/// The maximum number of results returned to Gnome Shell, unless configured otherwise.
+///
+/// Gnome Shell shows only a handful of results of every provider anyway.
+const DEFAULT_MAX_RESULTS: usize = 20;
+
+/// A search provider defined in a configuration file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProviderConfig {
+    /// A human readable label for this provider.
+    label: String,
+    /// The ID (that is, the filename) of the desktop file of the corresponding app.
+    desktop_id: String,
+    /// The relative object path to expose this provider at.
+    relative_obj_path: String,
+    /// The ID of the flatpak app, if the product is installed as flatpak.
+    flatpak_id: Option<String>,
+    /// The vendor configuration directory.
+    vendor_dir: String,
+    /// A glob for configuration directories inside the vendor directory.
+    config_glob: String,
+    /// The file name for recent projects.
+    #[serde(default = "default_projects_filename")]
+    projects_filename: String,
+}
+
+fn default_projects_filename() -> String {
+    "recentProjects.xml".to_string()
+}
+
+/// Whether `id` is a valid flatpak app ID, e.g. `com.jetbrains.DataGrip`.
+///
+/// An app ID has at least three elements separated by dots; every element consists of ASCII
+/// letters, digits, `_` and `-`, and does not start with a digit.
+fn is_valid_flatpak_id(id: &str) -> bool {
+    id.len() <= 255
+        && 3 <= id.split('.').count()
+        && id.split('.').all(|element| {
+            element
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && element
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        })
+}
+
+impl ProviderConfig {
+    /// Get the provider definition for this configuration.
+    fn definition(&self) -> ProviderDefinition<'_> {
+        ProviderDefinition {
+            label: &self.label,
+            desktop_id: &self.desktop_id,
+            relative_obj_path: &self.relative_obj_path,
+            config: ConfigLocation {
+                config_home: self
+                    .flatpak_id
+                    .as_deref()
+                    .map_or(ConfigHome::User, ConfigHome::Flatpak),
+                vendor_dir: &self.vendor_dir,
+                config_glob: &self.config_glob,
+                projects_filename: &self.projects_filename,
+            },
+        }
+    }
+
+    /// Validate this provider configuration.
+    fn validate(&self) -> Result<()> {
+        if self.label.trim().is_empty() {
+            return Err(anyhow!("Empty label"));
+        }
+        if !self.desktop_id.ends_with(".desktop") || self.desktop_id.contains('/') {
+            return Err(anyhow!("Invalid desktop ID {}", self.desktop_id));
+        }
+        let valid_obj_path = self.relative_obj_path.split('/').all(|element| {
+            !element.is_empty()
+                && element
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        if !valid_obj_path {
+            return Err(anyhow!(
+                "Invalid relative object path {}",
+                self.relative_obj_path
+            ));
+        }
+        if let Some(flatpak_id) = self
+            .flatpak_id
+            .as_deref()
+            .filter(|id| !is_valid_flatpak_id(id))
+        {
+            return Err(anyhow!("Invalid flatpak ID {}", flatpak_id));
+        }
+        let valid_vendor_dir = !self.vendor_dir.is_empty()
+            && Path::new(&self.vendor_dir)
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)));
+        if !valid_vendor_dir {
+            return Err(anyhow!("Invalid vendor directory {}", self.vendor_dir));
+        }
+        globwalk::GlobWalkerBuilder::new(&self.vendor_dir, &self.config_glob)
+            .build()
+            .with_context(|| format!("Invalid config glob {}", self.config_glob))?;
+        if self.projects_filename.is_empty() || self.projects_filename.contains('/') {
+            return Err(anyhow!(
+                "Invalid projects filename {}",
+                self.projects_filename
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Settings for a single search provider.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProviderSettings {
+    /// How to match recent projects against search terms.
+    match_mode: Option<MatchMode>,
+    /// How to handle recent projects whose directory no longer exists.
+    missing_projects: Option<MissingProjects>,
+    /// The template for descriptions of recent projects.
+    description: Option<DescriptionTemplate>,
+    /// What to launch for a search which does not match exactly one project.
+    launch_search: Option<LaunchSearchFallback>,
+    /// The maximum number of results.
+    max_results: Option<usize>,
+    /// The minimum score of results.
+    min_score: Option<f64>,
+}
+
+/// Validate the result limits `max_results` and `min_score`.
+fn validate_limits(max_results: Option<usize>, min_score: Option<f64>) -> Result<()> {
+    if max_results == Some(0) {
+        return Err(anyhow!("max_results must be at least 1"));
+    }
+    if let Some(min_score) = min_score.filter(|score| !(score.is_finite() && 0.0 <= *score)) {
+        return Err(anyhow!("Invalid min_score {}", min_score));
+    }
+    Ok(())
+}
+
+/// The configuration of this service.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// How to match recent projects against search terms, unless set for a specific provider.
+    match_mode: Option<MatchMode>,
+    /// How to handle recent projects whose directory no longer exists, unless set for a
+    /// specific provider.
+    missing_projects: Option<MissingProjects>,
+    /// The template for descriptions of recent projects, unless set for a specific provider.
+    description: Option<DescriptionTemplate>,
+    /// What to launch for a search which does not match exactly one project, unless set for
+    /// a specific provider.
+    launch_search: Option<LaunchSearchFallback>,
+    /// The maximum number of results, unless set for a specific provider.
+    max_results: Option<usize>,
+    /// The minimum score of results, unless set for a specific provider.
+    min_score: Option<f64>,
+    /// Additional search providers.
+    #[serde(default)]
+    providers: Vec<ProviderConfig>,
+    /// Settings for specific providers, by relative object path.
+    #[serde(default)]
+    settings: HashMap<String, ProviderSettings>,
+}
+
+impl Config {
+    /// Parse configuration from `contents`.
+    fn parse(contents: &str) -> Result<Config> {
+        let config: Config = toml::from_str(contents)?;
+        for provider in &config.providers {
+            provider
+                .validate()
+                .with_context(|| format!("Invalid provider {}", provider.label))?;
+        }
+        validate_limits(config.max_results, config.min_score)?;
+        for (relative_obj_path, settings) in &config.settings {
+            validate_limits(settings.max_results, settings.min_score)
+                .with_context(|| format!("Invalid settings for {}", relative_obj_path))?;
+        }
+        Ok(config)
+    }
+
+    /// Load configuration from `path`.
+    ///
+    /// Return an empty configuration if `path` does not exist.
+    fn load_from(path: &Path) -> Result<Config> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                info!("Loading configuration from {}", path.display());
+                Config::parse(&contents).with_context(|| {
+                    format!("Failed to load configuration from {}", path.display())
+                })
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                debug!("No configuration at {}", path.display());
+                Ok(Config::default())
+            }
+            Err(error) => Err(error)
+                .with_context(|| format!("Failed to read configuration from {}", path.display())),
+        }
+    }
+
+    /// Merge `other` into this configuration.
+    ///
+    /// Settings in `other` take precedence.
+    fn merge(mut self, other: Config) -> Config {
+        self.match_mode = other.match_mode.or(self.match_mode);
+        self.missing_projects = other.missing_projects.or(self.missing_projects);
+        self.description = other.description.or(self.description);
+        self.launch_search = other.launch_search.or(self.launch_search);
+        self.max_results = other.max_results.or(self.max_results);
+        self.min_score = other.min_score.or(self.min_score);
+        self.providers.extend(other.providers);
+        self.settings.extend(other.settings);
+        self
+    }
+
+    /// Check that all providers have a unique desktop ID and object path.
+    fn check_unique_providers(&self) -> Result<()> {
+        let mut desktop_ids = HashSet::new();
+        let mut objpaths = HashSet::new();
+        for provider in self.providers() {
+            if !desktop_ids.insert(provider.desktop_id) {
+                return Err(anyhow!(
+                    "Duplicate desktop ID {} for provider {}",
+                    provider.desktop_id,
+                    provider.label
+                ));
+            }
+            if !objpaths.insert(provider.objpath()) {
+                return Err(anyhow!(
+                    "Duplicate object path {} for provider {}",
+                    provider.objpath(),
+                    provider.label
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that settings only refer to existing providers.
+    fn check_settings(&self) -> Result<()> {
+        for relative_obj_path in self.settings.keys() {
+            if !self
+                .providers()
+                .any(|p| p.relative_obj_path == relative_obj_path)
+            {
+                return Err(anyhow!(
+                    "Settings for unknown provider {}",
+                    relative_obj_path
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Load and merge configuration from all `paths`, in this order.
+    ///
+    /// Log and ignore files which fail to load, or which conflict with previous files or
+    /// built-in providers, to keep serving all other providers.
+    fn load_files(paths: &[PathBuf]) -> Config {
+        let mut config = Config::default();
+        for path in paths {
+            let merged = Config::load_from(path).and_then(|other| {
+                let merged = config.clone().merge(other);
+                merged.check_unique_providers()?;
+                merged.check_settings()?;
+                Ok(merged)
+            });
+            match merged {
+                Ok(merged) => config = merged,
+                Err(error) => error!(
+                    "Ignoring configuration from {}: {:#}",
+                    path.display(),
+                    error
+                ),
+            }
+        }
+        config
+    }
+
+    /// Load the system-wide and the user configuration.
+    ///
+    /// Read the system-wide configuration from `/etc/gnome-search-providers-jetbrains/config.toml`
+    /// and the user configuration from `$XDG_CONFIG_HOME/gnome-search-providers-jetbrains/config.toml`,
+    /// and merge both; ignore invalid files.
+    pub fn load() -> Config {
+        let mut paths = vec![Path::new(SYSTEM_CONFIG_DIR).join(CONFIG_FILE_NAME)];
+        if let Some(user_config_file) = user_config_file() {
+            paths.push(user_config_file);
+        }
+        Config::load_files(&paths)
+    }
+
+    /// Get the settings for the given `provider`.
+    fn settings(&self, provider: &ProviderDefinition) -> Option<&ProviderSettings> {
+        self.settings.get(provider.relative_obj_path)
+    }
+
+    /// How to match recent projects against search terms, unless set for a specific provider.
+    pub fn default_match_mode(&self) -> MatchMode {
+        self.match_mode.unwrap_or_default()
+    }
+
+    /// How the given `provider` matches recent projects against search terms.
+    pub fn match_mode(&self, provider: &ProviderDefinition) -> MatchMode {
+        self.settings(provider)
+            .and_then(|s| s.match_mode)
+            .or(self.match_mode)
+            .unwrap_or_default()
+    }
+
+    /// How the given `provider` handles recent projects whose directory no longer exists.
+    pub fn missing_projects(&self, provider: &ProviderDefinition) -> MissingProjects {
+        self.settings(provider)
+            .and_then(|s| s.missing_projects)
+            .or(self.missing_projects)
+            .unwrap_or_default()
+    }
+
+    /// What to launch for a search which does not match exactly one project, unless set for a
+    /// specific provider.
+    pub fn default_launch_search(&self) -> LaunchSearchFallback {
+        self.launch_search.unwrap_or_default()
+    }
+
+    /// What the given `provider` launches for a search which does not match exactly one project.
+    pub fn launch_search(&self, provider: &ProviderDefinition) -> LaunchSearchFallback {
+        self.settings(provider)
+            .and_then(|s| s.launch_search)
+            .or(self.launch_search)
+            .unwrap_or_default()
+    }
+
+    /// Limits for search results, unless set for a specific provider.
+    pub fn default_result_limits(&self) -> ResultLimits {
+        ResultLimits {
+            max_results: Some(self.max_results.unwrap_or(DEFAULT_MAX_RESULTS)),
+            min_score: self.min_score.unwrap_or_default(),
+        }
+    }
+
+    /// Limits for search results of the given `provider`.
+    pub fn result_limits(&self, provider: &ProviderDefinition) -> ResultLimits {
+        let settings = self.settings(provider);
+        ResultLimits {
+            max_results: Some(
+                settings
+                    .and_then(|s| s.max_results)
+                    .or(self.max_results)
+                    .unwrap_or(DEFAULT_MAX_RESULTS),
+            ),
+            min_score: settings
+                .and_then(|s| s.min_score)
+                .or(self.min_score)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The template for descriptions of recent projects of the given `provider`.
+    pub fn description(&self, provider: &ProviderDefinition) -> DescriptionTemplate {
+        self.settings(provider)
+            .and_then(|s| s.description.as_ref())
+            .or(self.description.as_ref())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// All search providers, i.e. the built-in providers and the providers from configuration.
+    pub fn providers(&self) -> impl Iterator<Item = ProviderDefinition<'_>> {
+        PROVIDERS
+            .iter()
+            .copied()
+            .chain(self.providers.iter().map(ProviderConfig::definition))
+    }
+
+    /// Whether `provider` is defined in a configuration file, rather than built in.
+    pub fn is_configured(&self, provider: &ProviderDefinition) -> bool {
+        self.providers
+            .iter()
+            .any(|configured| configured.relative_obj_path == provider.relative_obj_path)
+    }
+}
+
+/// Get the path of the configuration file of the current user.
+fn user_config_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join(CONFIG_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    use super::*;
+
+    const DATAGRIP: &str = r#"
+[[providers]]
+label = "DataGrip (toolbox)"
+desktop_id = "jetbrains-datagrip.desktop"
+relative_obj_path = "toolbox/datagrip"
+vendor_dir = "JetBrains"
+config_glob = "DataGrip*"
+"#;
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn parse_provider() {
+        let config = Config::parse(DATAGRIP).unwrap();
+        assert_eq!(
+            config.providers,
+            vec![ProviderConfig {
+                label: "DataGrip (toolbox)".to_string(),
+                desktop_id: "jetbrains-datagrip.desktop".to_string(),
+                relative_obj_path: "toolbox/datagrip".to_string(),
+                flatpak_id: None,
+                vendor_dir: "JetBrains".to_string(),
+                config_glob: "DataGrip*".to_string(),
+                projects_filename: "recentProjects.xml".to_string(),
+            }]
+        );
+        let definition = config.providers[0].definition();
+        assert_eq!(
+            definition.objpath(),
+            "/de/swsnr/searchprovider/jetbrains/toolbox/datagrip"
+        );
+        assert!(matches!(definition.config.config_home, ConfigHome::User));
+    }
+
+    #[test]
+    fn parse_flatpak_provider() {
+        let config = Config::parse(
+            r#"
+[[providers]]
+label = "DataGrip (flatpak)"
+desktop_id = "com.jetbrains.DataGrip.desktop"
+relative_obj_path = "flatpak/datagrip"
+flatpak_id = "com.jetbrains.DataGrip"
+vendor_dir = "JetBrains"
+config_glob = "DataGrip*"
+"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            config.providers[0].definition().config.config_home,
+            ConfigHome::Flatpak("com.jetbrains.DataGrip")
+        ));
+    }
+
+    #[test]
+    fn providers_include_builtin_providers() {
+        let config = Config::parse(DATAGRIP).unwrap();
+        let labels: Vec<&str> = config.providers().map(|p| p.label).collect();
+        assert_eq!(labels.len(), PROVIDERS.len() + 1);
+        assert_eq!(labels[0], PROVIDERS[0].label);
+        assert_eq!(labels.last(), Some(&"DataGrip (toolbox)"));
+        assert!(config.check_unique_providers().is_ok());
+        assert!(!config.is_configured(&PROVIDERS[0]));
+        assert!(config.is_configured(&config.providers[0].definition()));
+    }
+
+    #[test]
+    fn match_mode_per_provider() {
+        let config = Config::parse(&format!(
+            "match_mode = \"fuzzy\"\n{}\n[settings.\"toolbox/idea\"]\nmatch_mode = \"substring\"",
+            DATAGRIP
+        ))
+        .unwrap();
+        assert!(config.check_settings().is_ok());
+        let providers: Vec<ProviderDefinition> = config.providers().collect();
+        let idea = providers
+            .iter()
+            .find(|p| p.relative_obj_path == "toolbox/idea")
+            .unwrap();
+        let datagrip = providers.last().unwrap();
+        assert_eq!(config.match_mode(idea), MatchMode::Substring);
+        assert_eq!(config.match_mode(datagrip), MatchMode::Fuzzy);
+
+        assert_eq!(Config::default().match_mode(datagrip), MatchMode::Substring);
+    }
+
+    #[test]
+    fn missing_projects_per_provider() {
+        let config = Config::parse(
+            "missing_projects = \"show\"\n[settings.\"toolbox/idea\"]\nmissing_projects = \"hide\"",
+        )
+        .unwrap();
+        let idea = PROVIDERS
+            .iter()
+            .find(|p| p.relative_obj_path == "toolbox/idea")
+            .unwrap();
+        let clion = PROVIDERS
+            .iter()
+            .find(|p| p.relative_obj_path == "toolbox/clion")
+            .unwrap();
+        assert_eq!(config.missing_projects(idea), MissingProjects::Hide);
+        assert_eq!(config.missing_projects(clion), MissingProjects::Show);
+        assert_eq!(
+            Config::default().missing_projects(clion),
+            MissingProjects::Hide
+        );
+    }
+
+    #[test]
+    fn description_per_provider() {
+        let config = Config::parse(
+            "description = \"{short_path}\"\n[settings.\"toolbox/idea\"]\ndescription = \"{path}[ on {branch}]\"",
+        )
+        .unwrap();
+        let idea = PROVIDERS
+            .iter()
+            .find(|p| p.relative_obj_path == "toolbox/idea")
+            .unwrap();
+        let clion = PROVIDERS
+            .iter()
+            .find(|p| p.relative_obj_path == "toolbox/clion")
+            .unwrap();
+        assert_eq!(
+            config.description(idea),
+            DescriptionTemplate::from_str("{path}[ on {branch}]").unwrap()
+        );
+        assert_eq!(
+            config.description(clion),
+            DescriptionTemplate::from_str("{short_path}").unwrap()
+        );
+        assert_eq!(
+            Config::default().description(clion),
+            DescriptionTemplate::default()
+        );
+    }
+
+    #[test]
+    fn reject_invalid_description() {
+        assert!(Config::parse("description = \"{foo}\"").is_err());
+    }
+
+    #[test]
+    fn launch_search_per_provider() {
+        let config = Config::parse(
+            "launch_search = \"best-match\"\n[settings.\"toolbox/idea\"]\nlaunch_search = \"nothing\"",
+        )
+        .unwrap();
+        let idea = PROVIDERS
+            .iter()
+            .find(|p| p.relative_obj_path == "toolbox/idea")
+            .unwrap();
+        let clion = PROVIDERS
+            .iter()
+            .find(|p| p.relative_obj_path == "toolbox/clion")
+            .unwrap();
+        assert_eq!(config.launch_search(idea), LaunchSearchFallback::Nothing);
+        assert_eq!(config.launch_search(clion), LaunchSearchFallback::BestMatch);
+        assert_eq!(
+            config.default_launch_search(),
+            LaunchSearchFallback::BestMatch
+        );
+        assert_eq!(
+            Config::default().launch_search(clion),
+            LaunchSearchFallback::App
+        );
+    }
+
+    #[test]
+    fn result_limits_per_provider() {
+        let config = Config::parse(
+            "max_results = 5\n[settings.\"toolbox/idea\"]\nmax_results = 10\nmin_score = 2.5",
+        )
+        .unwrap();
+        let idea = PROVIDERS
+            .iter()
+            .find(|p| p.relative_obj_path == "toolbox/idea")
+            .unwrap();
+        let clion = PROVIDERS
+            .iter()
+            .find(|p| p.relative_obj_path == "toolbox/clion")
+            .unwrap();
+        assert_eq!(
+            config.result_limits(idea),
+            ResultLimits {
+                max_results: Some(10),
+                min_score: 2.5
+            }
+        );
+        assert_eq!(
+            config.result_limits(clion),
+            ResultLimits {
+                max_results: Some(5),
+                min_score: 0.0
+            }
+        );
+        assert_eq!(config.default_result_limits(), config.result_limits(clion));
+        assert_eq!(
+            Config::default().result_limits(clion),
+            ResultLimits {
+                max_results: Some(DEFAULT_MAX_RESULTS),
+                min_score: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn reject_invalid_result_limits() {
+        for contents in &[
+            "max_results = 0",
+            "min_score = -1.0",
+            "min_score = nan",
+            "[settings.\"toolbox/idea\"]\nmax_results = 0",
+        ] {
+            assert!(
+                Config::parse(contents).is_err(),
+                "Not rejected: {}",
+                contents
+            );
+        }
+    }
+
+    #[test]
+    fn merge_settings() {
+        let system = Config::parse("match_mode = \"fuzzy\"").unwrap();
+        let user =
+            Config::parse("[settings.\"toolbox/idea\"]\nmatch_mode = \"substring\"").unwrap();
+        let config = system.merge(user);
+        assert_eq!(config.match_mode, Some(MatchMode::Fuzzy));
+        assert_eq!(
+            config.settings["toolbox/idea"].match_mode,
+            Some(MatchMode::Substring)
+        );
+    }
+
+    #[test]
+    fn reject_settings_for_unknown_providers() {
+        let config = Config::parse("[settings.\"toolbox/foo\"]\nmatch_mode = \"fuzzy\"").unwrap();
+        assert!(config.check_settings().is_err());
+    }
+
+    #[test]
+    fn reject_unknown_fields() {
+        assert!(Config::parse(&format!("{}\nfoo = \"bar\"", DATAGRIP)).is_err());
+    }
+
+    #[test]
+    fn reject_invalid_providers() {
+        let invalid = [
+            (
+                "desktop_id = \"jetbrains-datagrip.desktop\"",
+                "desktop_id = \"jetbrains-datagrip\"",
+            ),
+            ("toolbox/datagrip", "toolbox/data-grip"),
+            ("toolbox/datagrip", "/toolbox/datagrip"),
+            (
+                "vendor_dir = \"JetBrains\"",
+                "vendor_dir = \"../JetBrains\"",
+            ),
+            ("vendor_dir = \"JetBrains\"", "vendor_dir = \"/JetBrains\""),
+            ("DataGrip*", "DataGrip[*"),
+            ("label = \"DataGrip (toolbox)\"", "label = \" \""),
+            ("vendor_dir", "flatpak_id = \"../..\"\nvendor_dir"),
+            (
+                "vendor_dir",
+                "flatpak_id = \"com/jetbrains/DataGrip\"\nvendor_dir",
+            ),
+            ("vendor_dir", "flatpak_id = \"com.jetbrains\"\nvendor_dir"),
+            ("vendor_dir", "flatpak_id = \"com..DataGrip\"\nvendor_dir"),
+            (
+                "vendor_dir",
+                "flatpak_id = \"com.1jetbrains.DataGrip\"\nvendor_dir",
+            ),
+        ];
+        for (from, to) in &invalid {
+            let contents = DATAGRIP.replace(from, to);
+            assert!(
+                Config::parse(&contents).is_err(),
+                "Configuration not rejected: {}",
+                contents
+            );
+        }
+    }
+
+    #[test]
+    fn ignore_invalid_files() {
+        let dir = std::env::temp_dir().join(format!(
+            "gnome-search-providers-jetbrains-config-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let system = dir.join("system.toml");
+        let user = dir.join("user.toml");
+        std::fs::write(&system, format!("match_mode = \"fuzzy\"\n{}", DATAGRIP)).unwrap();
+        std::fs::write(&user, "max_results = 0").unwrap();
+        let config = Config::load_files(&[system.clone(), user.clone()]);
+        assert_eq!(config.match_mode, Some(MatchMode::Fuzzy));
+        assert_eq!(config.max_results, None);
+        assert_eq!(config.providers().count(), PROVIDERS.len() + 1);
+
+        // A duplicate provider in the user configuration leaves the system configuration intact
+        std::fs::write(&user, DATAGRIP).unwrap();
+        let config = Config::load_files(&[system, user]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(config.providers().count(), PROVIDERS.len() + 1);
+    }
+
+    #[test]
+    fn reject_duplicate_providers() {
+        let config =
+            Config::parse(&DATAGRIP.replace("jetbrains-datagrip.desktop", PROVIDERS[0].desktop_id))
+                .unwrap();
+        assert!(config.check_unique_providers().is_err());
+
+        let config =
+            Config::parse(&DATAGRIP.replace("toolbox/datagrip", PROVIDERS[0].relative_obj_path))
+                .unwrap();
+        assert!(config.check_unique_providers().is_err());
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A DBus interface to debug search providers.
+
+use std::rc::Rc;
+
+use gnome_search_provider_common::app::AppLaunchItem;
+use gnome_search_provider_common::matching::ItemsSource;
+use log::{debug, trace};
+use zbus::dbus_interface;
+
+use crate::source::{AllProjectsSource, JetbrainsProjectsSource};
+
+/// A source of recent projects which tells its projects without loading them.
+pub trait ProjectsSnapshot {
+    /// Get the recent projects of the last successful load as triples of ID, name and
+    /// description.
+    ///
+    /// Never load recent projects, so that debugging does not change what the search provider
+    /// finds.
+    fn snapshot(&self) -> Vec<(String, String, String)>;
+}
+
+/// Describe `item` with `description`, or with its own description or its target.
+fn describe(item: AppLaunchItem, description: Option<String>) -> (String, String) {
+    let description = description
+        .or_else(|| item.description.clone())
+        .unwrap_or_else(|| item.target.description().to_string());
+    (item.name, description)
+}
+
+impl ProjectsSnapshot for JetbrainsProjectsSource<'_> {
+    fn snapshot(&self) -> Vec<(String, String, String)> {
+        self.snapshot_items()
+            .into_iter()
+            .map(|(id, item)| {
+                let (name, description) = describe(item, self.describe_item(&id));
+                (id, name, description)
+            })
+            .collect()
+    }
+}
+
+impl ProjectsSnapshot for AllProjectsSource<'_> {
+    fn snapshot(&self) -> Vec<(String, String, String)> {
+        self.snapshot_items()
+            .into_iter()
+            .map(|(id, ((index, source_id), item))| {
+                let description = self.sources[index].1.describe_item(&source_id);
+                let (name, description) = describe(item, description);
+                (id, name, description)
+            })
+            .collect()
+    }
+}
+
+/// A shared source of recent projects.
+impl<S: ProjectsSnapshot> ProjectsSnapshot for Rc<S> {
+    fn snapshot(&self) -> Vec<(String, String, String)> {
+        self.as_ref().snapshot()
+    }
+}
+
+/// Debugging information about a search provider.
+///
+/// Served next to the search provider at the same object path.
+pub struct DebugInterface<S> {
+    /// The name of the search provider, for logging.
+    name: String,
+    /// The source of the items of the search provider.
+    items: S,
+    /// The sources of recent projects the search provider reads.
+    sources: Vec<Rc<JetbrainsProjectsSource<'static>>>,
+}
+
+impl<S: ProjectsSnapshot> DebugInterface<S> {
+    /// Create a debug interface for the search provider with the given `name`.
+    ///
+    /// The search provider gets its items from `items`, which reads recent projects from
+    /// all `sources`.
+    pub fn new(name: String, items: S, sources: Vec<Rc<JetbrainsProjectsSource<'static>>>) -> Self {
+        Self {
+            name,
+            items,
+            sources,
+        }
+    }
+}
+
+/// Get the directory which contains the vendor configuration directory of `source`.
+fn config_home(source: &JetbrainsProjectsSource) -> zbus::fdo::Result<std::path::PathBuf> {
+    source
+        .config_home()
+        .map_err(|error| zbus::fdo::Error::Failed(format!("{:#}", error)))
+}
+
+/// The DBus interface to debug a search provider.
+#[dbus_interface(name = "de.swsnr.searchprovider.Jetbrains.Debug")]
+impl<S: ProjectsSnapshot + 'static> DebugInterface<S> {
+    /// Get the configuration directories of all installed product versions.
+    ///
+    /// Return directories ordered by version, latest version first, for every IDE in turn.
+    fn config_dirs(&self) -> zbus::fdo::Result<Vec<String>> {
+        trace!("Enter ConfigDirs() for {}", self.name);
+        let mut dirs = Vec::new();
+        for source in &self.sources {
+            let config_home = config_home(source)?;
+            dirs.extend(
+                source
+                    .config
+                    .find_config_dirs(&config_home)
+                    .into_iter()
+                    .map(|dir| dir.into_path().display().to_string()),
+            );
+        }
+        debug!("Found config dirs {:?} for {}", dirs, self.name);
+        Ok(dirs)
+    }
+
+    /// Get the recent projects files the provider reads, whether they exist or not.
+    fn recent_projects_files(&self) -> zbus::fdo::Result<Vec<String>> {
+        trace!("Enter RecentProjectsFiles() for {}", self.name);
+        let mut files = Vec::new();
+        for source in &self.sources {
+            let config_home = config_home(source)?;
+            files.extend(
+                source
+                    .config
+                    .recent_projects_files(&config_home, source.versions)
+                    .into_iter()
+                    .map(|file| file.display().to_string()),
+            );
+        }
+        Ok(files)
+    }
+
+    /// Get all recent projects of the last successful load as triples of ID, name and
+    /// description.
+    ///
+    /// Do not load recent projects; return no projects if the provider didn't load any yet.
+    fn items(&self) -> Vec<(String, String, String)> {
+        trace!("Enter Items() for {}", self.name);
+        self.items.snapshot()
+    }
+
+    /// Get the error of the last attempt to load recent projects.
+    ///
+    /// If the provider reads recent projects of several IDEs, prefix the error of every IDE
+    /// with its desktop ID, one per line.  Return an empty string if the last attempt succeeded.
+    fn last_error(&self) -> String {
+        trace!("Enter LastError() for {}", self.name);
+        match self.sources.as_slice() {
+            [source] => source.last_error.borrow().clone().unwrap_or_default(),
+            sources => sources
+                .iter()
+                .filter_map(|source| {
+                    source
+                        .last_error
+                        .borrow()
+                        .as_ref()
+                        .map(|error| format!("{}: {}", source.app_id, error))
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
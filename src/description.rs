@@ -0,0 +1,353 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Descriptions of search results.
+
+use std::convert::TryFrom;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Error, Result};
+use serde::Deserialize;
+
+/// A property of a recent project which a description can include.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// The full path of the project.
+    Path,
+    /// The path of the project, relative to `$HOME` if possible, e.g. `~/Code/mdcat`.
+    ShortPath,
+    /// The directory of the project, i.e. the directory which contains the solution for
+    /// Rider solutions, and the full path for all other projects.
+    Directory,
+    /// The names of all groups the project belongs to.
+    Groups,
+    /// The current Git branch of the project.
+    Branch,
+    /// How long ago the project was last opened, e.g. `3 days ago`.
+    Opened,
+    /// The IDE build which last opened the project, e.g. `IC-211.6693.111`.
+    Build,
+}
+
+impl FromStr for Field {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "path" => Ok(Field::Path),
+            "short_path" => Ok(Field::ShortPath),
+            "directory" => Ok(Field::Directory),
+            "groups" => Ok(Field::Groups),
+            "branch" => Ok(Field::Branch),
+            "opened" => Ok(Field::Opened),
+            "build" => Ok(Field::Build),
+            _ => Err(anyhow!("Unknown field {{{}}}", s)),
+        }
+    }
+}
+
+/// A segment of a description template.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// Literal text.
+    Text(String),
+    /// The value of a field.
+    Field(Field),
+    /// Segments to omit if any field in these segments has no value.
+    Optional(Vec<Segment>),
+}
+
+/// A template for the description of a recent project.
+///
+/// A template contains literal text and fields in braces, e.g. `{short_path}`.  Parts in
+/// brackets are optional: they disappear if any field inside has no value, e.g. the
+/// template `{short_path}[ on {branch}]` omits ` on ` for projects without a Git branch.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct DescriptionTemplate {
+    segments: Vec<Segment>,
+}
+
+impl DescriptionTemplate {
+    /// Render this template with the given `value` of every field.
+    ///
+    /// Fields without value render as empty text outside of optional parts.
+    pub fn render<F: FnMut(Field) -> Option<String>>(&self, mut value: F) -> String {
+        let mut description = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => description.push_str(text),
+                Segment::Field(field) => {
+                    description.push_str(&value(*field).unwrap_or_default());
+                }
+                Segment::Optional(segments) => {
+                    let rendered: Option<String> = segments
+                        .iter()
+                        .map(|segment| match segment {
+                            Segment::Text(text) => Some(text.clone()),
+                            Segment::Field(field) => value(*field),
+                            Segment::Optional(_) => None,
+                        })
+                        .collect();
+                    description.push_str(&rendered.unwrap_or_default());
+                }
+            }
+        }
+        description
+    }
+}
+
+impl Default for DescriptionTemplate {
+    /// The default template: the names of all groups, if any, and the full path.
+    fn default() -> Self {
+        DescriptionTemplate::from_str("[{groups}: ]{path}").unwrap()
+    }
+}
+
+impl FromStr for DescriptionTemplate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        // Segments of the current optional part, if any.
+        let mut optional: Option<Vec<Segment>> = None;
+        let mut text = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            let current = optional.as_mut().unwrap_or(&mut segments);
+            match c {
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(anyhow!("Unterminated field in {}", s)),
+                        }
+                    }
+                    if !text.is_empty() {
+                        current.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    current.push(Segment::Field(Field::from_str(&name)?));
+                }
+                '[' if optional.is_none() => {
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    optional = Some(Vec::new());
+                }
+                ']' if optional.is_some() => {
+                    let mut parts = optional.take().unwrap();
+                    if !text.is_empty() {
+                        parts.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Optional(parts));
+                }
+                '}' | '[' | ']' => return Err(anyhow!("Unexpected {} in {}", c, s)),
+                c => text.push(c),
+            }
+        }
+        if optional.is_some() {
+            return Err(anyhow!("Unterminated optional part in {}", s));
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(DescriptionTemplate { segments })
+    }
+}
+
+impl TryFrom<String> for DescriptionTemplate {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        DescriptionTemplate::from_str(&value)
+    }
+}
+
+/// Make `path` relative to `home`, e.g. `~/Code/mdcat`.
+///
+/// Return `path` as is if it's not inside `home`.
+pub fn home_relative_path(path: &str, home: &Path) -> String {
+    match Path::new(path).strip_prefix(home) {
+        Ok(relative) if relative.as_os_str().is_empty() => "~".to_string(),
+        Ok(relative) => format!("~/{}", relative.display()),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// Describe the given `age` in words, e.g. `3 days ago`.
+pub fn humanize_age(age: Duration) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+    const WEEK: u64 = 7 * DAY;
+    const MONTH: u64 = 30 * DAY;
+    const YEAR: u64 = 365 * DAY;
+    let seconds = age.as_secs();
+    let (count, unit) = match seconds {
+        s if s < MINUTE => return "just now".to_string(),
+        s if s < HOUR => (s / MINUTE, "minute"),
+        s if s < DAY => (s / HOUR, "hour"),
+        s if s < WEEK => (s / DAY, "day"),
+        s if s < MONTH => (s / WEEK, "week"),
+        s if s < YEAR => (s / MONTH, "month"),
+        s => (s / YEAR, "year"),
+    };
+    if count == 1 {
+        format!("1 {} ago", unit)
+    } else {
+        format!("{} {}s ago", count, unit)
+    }
+}
+
+/// Get the current Git branch of the repository at `path`.
+///
+/// Return the abbreviated commit ID for a detached `HEAD`, and `None` if `path` is not
+/// the root of a Git repository.
+pub fn git_branch(path: &Path) -> Option<String> {
+    let dot_git = path.join(".git");
+    let git_dir = if dot_git.is_file() {
+        // Worktrees and submodules have a .git file which points to the actual Git directory.
+        let contents = std::fs::read_to_string(&dot_git).ok()?;
+        path.join(contents.trim().strip_prefix("gitdir:")?.trim())
+    } else {
+        dot_git
+    };
+    std::fs::read_to_string(git_dir.join("HEAD"))
+        .ok()
+        .and_then(|head| parse_git_head(&head))
+}
+
+/// Parse the branch from the `contents` of a Git `HEAD` file.
+fn parse_git_head(contents: &str) -> Option<String> {
+    let head = contents.trim();
+    match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            Some(
+                reference
+                    .strip_prefix("refs/heads/")
+                    .unwrap_or(reference)
+                    .to_string(),
+            )
+        }
+        None if head.len() >= 7 && head.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Some(head[..7].to_string())
+        }
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn values(field: Field) -> Option<String> {
+        match field {
+            Field::Path => Some("/home/foo/dev/mdcat".to_string()),
+            Field::ShortPath => Some("~/dev/mdcat".to_string()),
+            Field::Directory => Some("/home/foo/dev/mdcat".to_string()),
+            Field::Branch => Some("main".to_string()),
+            Field::Opened => Some("3 days ago".to_string()),
+            Field::Groups | Field::Build => None,
+        }
+    }
+
+    #[test]
+    fn render_default_template() {
+        let template = DescriptionTemplate::default();
+        assert_eq!(template.render(values), "/home/foo/dev/mdcat");
+        assert_eq!(
+            template.render(|field| match field {
+                Field::Groups => Some("work, rust".to_string()),
+                field => values(field),
+            }),
+            "work, rust: /home/foo/dev/mdcat"
+        );
+    }
+
+    #[test]
+    fn render_template() {
+        let template = DescriptionTemplate::from_str(
+            "{short_path}[ on {branch}][ ({build})] · opened {opened}{build}",
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(values),
+            "~/dev/mdcat on main · opened 3 days ago"
+        );
+    }
+
+    #[test]
+    fn reject_invalid_templates() {
+        for template in &[
+            "{foo}",
+            "{path",
+            "path}",
+            "[{path}",
+            "{path}]",
+            "[[{path}]]",
+        ] {
+            assert!(
+                DescriptionTemplate::from_str(template).is_err(),
+                "Template not rejected: {}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn home_relative() {
+        let home = Path::new("/home/foo");
+        assert_eq!(
+            home_relative_path("/home/foo/dev/mdcat", home),
+            "~/dev/mdcat"
+        );
+        assert_eq!(home_relative_path("/home/foo", home), "~");
+        assert_eq!(home_relative_path("/home/foobar", home), "/home/foobar");
+        assert_eq!(home_relative_path("/srv/mdcat", home), "/srv/mdcat");
+    }
+
+    #[test]
+    fn humanized_ages() {
+        let ages = [
+            (30, "just now"),
+            (60, "1 minute ago"),
+            (5 * 60, "5 minutes ago"),
+            (3 * 3600, "3 hours ago"),
+            (24 * 3600, "1 day ago"),
+            (3 * 24 * 3600, "3 days ago"),
+            (15 * 24 * 3600, "2 weeks ago"),
+            (100 * 24 * 3600, "3 months ago"),
+            (800 * 24 * 3600, "2 years ago"),
+        ];
+        for (seconds, expected) in &ages {
+            assert_eq!(humanize_age(Duration::from_secs(*seconds)), *expected);
+        }
+    }
+
+    #[test]
+    fn git_head() {
+        assert_eq!(
+            parse_git_head("ref: refs/heads/main\n"),
+            Some("main".to_string())
+        );
+        assert_eq!(
+            parse_git_head("ref: refs/heads/feature/foo\n"),
+            Some("feature/foo".to_string())
+        );
+        assert_eq!(
+            parse_git_head("3b18e512dba79e4c8300dd08aeb37f8e728b8dad\n"),
+            Some("3b18e51".to_string())
+        );
+        assert_eq!(parse_git_head("garbage"), None);
+    }
+}
@@ -0,0 +1,418 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Diagnose problems with the installation of search providers.
+
+use std::fmt::{Display, Formatter};
+
+use gnome_search_provider_common::export::gio;
+
+use crate::config::Config;
+use crate::installed::{load_provider_files, search_provider_dirs, ProviderFile};
+use crate::recent::{read_recent_projects_file, RecentProjects};
+use crate::{ProviderDefinition, VersionSelection, ALL_PROJECTS_OBJ_PATH};
+
+/// The outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    /// Everything's fine.
+    Ok,
+    /// Something looks odd, but doesn't break the provider.
+    Warning,
+    /// Something breaks the provider.
+    Error,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Error => "error",
+        };
+        f.pad(label)
+    }
+}
+
+/// The result of a single check.
+#[derive(Debug, PartialEq)]
+pub struct Check {
+    /// The outcome.
+    pub status: Status,
+    /// What was checked, and what was found.
+    pub message: String,
+}
+
+impl Check {
+    fn ok<S: Into<String>>(message: S) -> Self {
+        Self {
+            status: Status::Ok,
+            message: message.into(),
+        }
+    }
+
+    fn warning<S: Into<String>>(message: S) -> Self {
+        Self {
+            status: Status::Warning,
+            message: message.into(),
+        }
+    }
+
+    fn error<S: Into<String>>(message: S) -> Self {
+        Self {
+            status: Status::Error,
+            message: message.into(),
+        }
+    }
+}
+
+/// Check that a search provider file for `provider` exists among `files`, with the right
+/// object path.
+fn check_provider_file(provider: &ProviderDefinition, files: &[ProviderFile]) -> Check {
+    let candidates: Vec<&ProviderFile> = files
+        .iter()
+        .filter(|file| file.desktop_id == provider.desktop_id)
+        .collect();
+    match candidates.as_slice() {
+        [] => Check::error(format!(
+            "No search provider file for {} in any of {}",
+            provider.desktop_id,
+            search_provider_dirs()
+                .iter()
+                .map(|dir| dir.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        [file] if file.object_path == provider.objpath() => Check::ok(format!(
+            "Search provider file {} found",
+            file.path.display()
+        )),
+        [file] => Check::error(format!(
+            "Search provider file {} has object path {}, expected {}",
+            file.path.display(),
+            file.object_path,
+            provider.objpath()
+        )),
+        files => Check::error(format!(
+            "Multiple search provider files for {}: {}",
+            provider.desktop_id,
+            files
+                .iter()
+                .map(|file| file.path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Check the configuration directories and recent projects of `provider`.
+fn check_recent_projects(provider: &ProviderDefinition, versions: VersionSelection) -> Vec<Check> {
+    let config_home = match provider.config.config_home.path() {
+        Some(config_home) => config_home,
+        None => return vec![Check::error("No configuration directory")],
+    };
+    let mut checks = Vec::new();
+    let dirs = provider.config.find_config_dirs(&config_home);
+    match dirs.first() {
+        None => {
+            checks.push(Check::error(format!(
+                "No configuration directory matches {} in {}; start the IDE once",
+                provider.config.config_glob,
+                config_home.join(provider.config.vendor_dir).display()
+            )));
+            return checks;
+        }
+        Some(dir) => checks.push(Check::ok(format!(
+            "Configuration directory {} found",
+            dir.path.display()
+        ))),
+    }
+    for file in provider
+        .config
+        .recent_projects_files(&config_home, versions)
+    {
+        let projects = read_recent_projects_file(&file);
+        checks.push(match projects {
+            Ok(RecentProjects {
+                projects,
+                error: None,
+            }) => Check::ok(format!(
+                "{} recent project(s) in {}",
+                projects.len(),
+                file.display()
+            )),
+            Ok(RecentProjects {
+                projects,
+                error: Some(error),
+            }) => Check::warning(format!(
+                "{:#}; read only {} recent project(s) before the error",
+                error,
+                projects.len()
+            )),
+            Err(_) if !file.exists() => Check::warning(format!(
+                "{} does not exist; open a project in the IDE",
+                file.display()
+            )),
+            Err(error) => Check::error(format!("{:#}", error)),
+        });
+    }
+    checks
+}
+
+/// Check `provider` end to end, with the given search provider `files`.
+///
+/// Return no checks if neither the app nor a search provider file for `provider` is
+/// installed, because then the provider is simply not in use.  If the provider is
+/// `configured` in a configuration file, always check its search provider file, because
+/// `make install` doesn't install search provider files for configured providers.
+fn check_provider(
+    provider: &ProviderDefinition,
+    versions: VersionSelection,
+    files: &[ProviderFile],
+    configured: bool,
+) -> Vec<Check> {
+    let app = gio::DesktopAppInfo::new(provider.desktop_id);
+    let mut provider_file = check_provider_file(provider, files);
+    if configured && provider_file.status == Status::Error {
+        provider_file.message.push_str(
+            "; install a search provider file for configured providers by hand, \
+             see \"Additional IDEs\" in the README",
+        );
+    }
+    match app {
+        None if provider_file.status == Status::Error && !configured => Vec::new(),
+        None => vec![
+            Check::warning(format!(
+                "Desktop file {} not found, the provider is disabled",
+                provider.desktop_id
+            )),
+            provider_file,
+        ],
+        Some(app) => {
+            let mut checks = vec![
+                Check::ok(format!(
+                    "Desktop file {} found{}",
+                    provider.desktop_id,
+                    app.filename()
+                        .map(|path| format!(" at {}", path.display()))
+                        .unwrap_or_default()
+                )),
+                provider_file,
+            ];
+            checks.extend(check_recent_projects(provider, versions));
+            checks
+        }
+    }
+}
+
+/// Check the provider for recent projects of all IDEs, with the given search provider `files`.
+///
+/// Return no checks if no search provider file for all projects is installed, because the
+/// provider is optional.
+fn check_all_projects_provider(files: &[ProviderFile]) -> Vec<Check> {
+    let candidates: Vec<&ProviderFile> = files
+        .iter()
+        .filter(|file| file.object_path == ALL_PROJECTS_OBJ_PATH)
+        .collect();
+    match candidates.as_slice() {
+        [] => Vec::new(),
+        [file] => {
+            let app = match gio::DesktopAppInfo::new(&file.desktop_id) {
+                Some(app) => Check::ok(format!(
+                    "Desktop file {} found{}",
+                    file.desktop_id,
+                    app.filename()
+                        .map(|path| format!(" at {}", path.display()))
+                        .unwrap_or_default()
+                )),
+                None => Check::error(format!(
+                    "Desktop file {} of {} not found, the provider is disabled",
+                    file.desktop_id,
+                    file.path.display()
+                )),
+            };
+            vec![
+                Check::ok(format!(
+                    "Search provider file {} found",
+                    file.path.display()
+                )),
+                app,
+            ]
+        }
+        files => vec![Check::error(format!(
+            "Multiple search provider files for {}: {}",
+            ALL_PROJECTS_OBJ_PATH,
+            files
+                .iter()
+                .map(|file| file.path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))],
+    }
+}
+
+/// Check all providers in `config`, and print a report to standard output.
+///
+/// Return the worst status of all checks.
+pub fn doctor(config: &Config, versions: VersionSelection) -> Status {
+    let (files, errors) = load_provider_files(&search_provider_dirs());
+    let file_checks: Vec<Check> = errors
+        .iter()
+        .map(|error| Check::warning(format!("{:#}", error)))
+        .collect();
+    let mut worst = Status::Ok;
+    let mut report = |title: &str, checks: &[Check]| {
+        println!("{}", title);
+        for check in checks {
+            println!("  {:<7}  {}", check.status, check.message);
+            worst = worst.max(check.status);
+        }
+    };
+    if !file_checks.is_empty() {
+        report("Search provider files", &file_checks);
+    }
+    let mut unused = Vec::new();
+    for provider in config.providers() {
+        let checks = check_provider(&provider, versions, &files, config.is_configured(&provider));
+        if checks.is_empty() {
+            unused.push(provider.label);
+        } else {
+            report(
+                &format!("{} [{}]", provider.label, provider.relative_obj_path),
+                &checks,
+            );
+        }
+    }
+    let checks = check_all_projects_provider(&files);
+    if checks.is_empty() {
+        unused.push("All projects");
+    } else {
+        report("All projects [all]", &checks);
+    }
+    if !unused.is_empty() {
+        unused.sort_unstable();
+        println!("Not installed: {}", unused.join(", "));
+    }
+    worst
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::BUSNAME;
+
+    fn provider() -> &'static ProviderDefinition<'static> {
+        crate::PROVIDERS
+            .iter()
+            .find(|p| p.relative_obj_path == "toolbox/clion")
+            .unwrap()
+    }
+
+    fn provider_file(name: &str, desktop_id: &str, object_path: &str) -> ProviderFile {
+        ProviderFile {
+            path: PathBuf::from(name),
+            desktop_id: desktop_id.to_string(),
+            object_path: object_path.to_string(),
+            bus_name: BUSNAME.to_string(),
+        }
+    }
+
+    #[test]
+    fn provider_file_found() {
+        let files = vec![
+            provider_file("idea.ini", "jetbrains-idea.desktop", "/foo"),
+            provider_file(
+                "clion.ini",
+                "jetbrains-clion.desktop",
+                "/de/swsnr/searchprovider/jetbrains/toolbox/clion",
+            ),
+        ];
+        assert_eq!(check_provider_file(provider(), &files).status, Status::Ok);
+    }
+
+    #[test]
+    fn provider_file_missing_or_inconsistent() {
+        let wrong_path = vec![provider_file(
+            "clion.ini",
+            "jetbrains-clion.desktop",
+            "/de/swsnr/searchprovider/jetbrains/flatpak/clion",
+        )];
+        let duplicate = vec![
+            provider_file(
+                "a.ini",
+                "jetbrains-clion.desktop",
+                "/de/swsnr/searchprovider/jetbrains/toolbox/clion",
+            ),
+            provider_file(
+                "b.ini",
+                "jetbrains-clion.desktop",
+                "/de/swsnr/searchprovider/jetbrains/toolbox/clion",
+            ),
+        ];
+        for files in &[Vec::new(), wrong_path, duplicate] {
+            assert_eq!(
+                check_provider_file(provider(), files).status,
+                Status::Error,
+                "{:?}",
+                files
+            );
+        }
+    }
+
+    #[test]
+    fn configured_provider_without_provider_file() {
+        let provider = ProviderDefinition {
+            label: "DataGrip (toolbox)",
+            desktop_id: "no-such-datagrip.desktop",
+            relative_obj_path: "toolbox/datagrip",
+            ..*provider()
+        };
+        let files = [provider_file("idea.ini", "jetbrains-idea.desktop", "/foo")];
+        assert!(check_provider(&provider, VersionSelection::Latest, &files, false).is_empty());
+        let checks = check_provider(&provider, VersionSelection::Latest, &files, true);
+        assert_eq!(
+            checks.iter().map(|c| c.status).collect::<Vec<_>>(),
+            vec![Status::Warning, Status::Error]
+        );
+        assert!(
+            checks[1].message.contains("by hand"),
+            "{}",
+            checks[1].message
+        );
+    }
+
+    #[test]
+    fn all_projects_provider() {
+        let other = provider_file("idea.ini", "jetbrains-idea.desktop", "/foo");
+        let all = provider_file(
+            "all.ini",
+            "no-such-app.desktop",
+            "/de/swsnr/searchprovider/jetbrains/all",
+        );
+        assert!(check_all_projects_provider(&[other]).is_empty());
+        let checks = check_all_projects_provider(std::slice::from_ref(&all));
+        assert_eq!(
+            checks.iter().map(|c| c.status).collect::<Vec<_>>(),
+            vec![Status::Ok, Status::Error]
+        );
+        let duplicate = check_all_projects_provider(&[
+            all,
+            provider_file(
+                "all-2.ini",
+                "jetbrains-toolbox.desktop",
+                "/de/swsnr/searchprovider/jetbrains/all",
+            ),
+        ]);
+        assert_eq!(
+            duplicate.iter().map(|c| c.status).collect::<Vec<_>>(),
+            vec![Status::Error]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Search provider files installed for Gnome Shell.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::debug;
+
+use gnome_search_provider_common::export::glib;
+
+use crate::BUSNAME;
+
+/// A search provider file installed for Gnome Shell.
+#[derive(Debug, PartialEq)]
+pub struct ProviderFile {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The desktop ID the file refers to.
+    pub desktop_id: String,
+    /// The object path the file refers to.
+    pub object_path: String,
+    /// The bus name the file refers to.
+    pub bus_name: String,
+}
+
+impl ProviderFile {
+    /// Load a search provider file from `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let keyfile = glib::KeyFile::new();
+        keyfile
+            .load_from_file(path, glib::KeyFileFlags::NONE)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        let get = |key: &str| {
+            keyfile
+                .string("Shell Search Provider", key)
+                .map(|value| value.to_string())
+                .with_context(|| format!("{} missing in {}", key, path.display()))
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            desktop_id: get("DesktopId")?,
+            object_path: get("ObjectPath")?,
+            bus_name: get("BusName")?,
+        })
+    }
+}
+
+/// The directories Gnome Shell loads search provider files from.
+///
+/// Gnome Shell only looks at system data directories, not at `$XDG_DATA_HOME`.
+pub fn search_provider_dirs() -> Vec<PathBuf> {
+    glib::system_data_dirs()
+        .into_iter()
+        .map(|dir| dir.join("gnome-shell").join("search-providers"))
+        .collect()
+}
+
+/// Load all search provider files in `dirs`.
+///
+/// Return the files which refer to our bus name, and errors of files which fail to load.
+pub fn load_provider_files(dirs: &[PathBuf]) -> (Vec<ProviderFile>, Vec<anyhow::Error>) {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    for dir in dirs {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(error) => {
+                debug!("Skipping {}: {}", dir.display(), error);
+                continue;
+            }
+        };
+        let paths = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ini"));
+        for path in paths {
+            match ProviderFile::load(&path) {
+                Ok(file) if file.bus_name == BUSNAME => files.push(file),
+                Ok(_) => {}
+                Err(error) => errors.push(error),
+            }
+        }
+    }
+    (files, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn load_provider_file() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("providers")
+            .join("de.swsnr.searchprovider.jetbrains.toolbox.clion.ini");
+        assert_eq!(
+            ProviderFile::load(&path).unwrap(),
+            ProviderFile {
+                path: path.clone(),
+                desktop_id: "jetbrains-clion.desktop".to_string(),
+                object_path: "/de/swsnr/searchprovider/jetbrains/toolbox/clion".to_string(),
+                bus_name: BUSNAME.to_string(),
+            }
+        );
+    }
+}
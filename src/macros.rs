@@ -0,0 +1,175 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Expand path macros in the configuration of Jetbrains products.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use elementtree::Element;
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use regex::{Captures, Regex};
+
+/// Path macros, such as `$USER_HOME$`, and their values.
+///
+/// Jetbrains products replace well-known paths with macros when writing paths to their
+/// configuration; users can define additional macros under "Path Variables" in the settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathMacros {
+    values: HashMap<String, String>,
+}
+
+impl PathMacros {
+    /// Create the built-in macros for the given `home` and `config_dir` of a product.
+    ///
+    /// `$PROJECT_DIR$` and `$APPLICATION_HOME_DIR$` are not known, because they refer to a
+    /// project and to the installation directory of a product respectively.
+    pub fn builtin(home: &str, config_dir: &str) -> Self {
+        let mut values = HashMap::new();
+        values.insert("USER_HOME".to_string(), home.to_string());
+        values.insert("APPLICATION_CONFIG_DIR".to_string(), config_dir.to_string());
+        Self { values }
+    }
+
+    /// Load the macros for the product configuration directory `config_dir`.
+    ///
+    /// Add custom path variables from `options/path.macros.xml` in `config_dir` to the
+    /// built-in macros; warn and skip custom variables if this file fails to load.
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        let home = dirs::home_dir()
+            .with_context(|| "$HOME directory required")?
+            .into_os_string()
+            .into_string()
+            .ok()
+            .with_context(|| "$HOME not a valid UTF-8 string")?;
+        let mut macros = Self::builtin(&home, &config_dir.to_string_lossy());
+        let file = config_dir.join("options").join("path.macros.xml");
+        if file.is_file() {
+            let custom = std::fs::File::open(&file)
+                .map_err(anyhow::Error::from)
+                .and_then(|source| macros.add_custom(source));
+            match custom {
+                Ok(()) => debug!("Loaded path macros from {}", file.display()),
+                Err(error) => warn!("Failed to read {}: {:#}", file.display(), error),
+            }
+        }
+        Ok(macros)
+    }
+
+    /// Add custom path variables from the contents of a `path.macros.xml` file in `reader`.
+    ///
+    /// Custom variables do not override built-in macros.  Expand macros in the values of
+    /// custom variables, e.g. `$USER_HOME$`, and skip variables whose value contains an
+    /// unknown macro.
+    pub fn add_custom<R: Read>(&mut self, reader: R) -> Result<()> {
+        let element = Element::from_reader(reader)?;
+        let macros = element
+            .find_all("component")
+            .filter(|component| component.get_attr("name") == Some("PathMacrosImpl"))
+            .flat_map(|component| component.find_all("macro"));
+        for element in macros {
+            if let (Some(name), Some(value)) = (element.get_attr("name"), element.get_attr("value"))
+            {
+                if self.values.contains_key(name) {
+                    continue;
+                }
+                match self.expand(value) {
+                    Ok(value) => {
+                        self.values.insert(name.to_string(), value);
+                    }
+                    Err(error) => warn!("Skipping path macro {}: {:#}", name, error),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Expand all macros in `path`.
+    ///
+    /// Fail if `path` contains an unknown macro, instead of returning a bogus path.
+    pub fn expand(&self, path: &str) -> Result<String> {
+        lazy_static! {
+            static ref MACRO: Regex = Regex::new(r"\$([A-Za-z_][A-Za-z0-9_]*)\$").unwrap();
+        }
+        let mut unknown = None;
+        let expanded = MACRO.replace_all(path, |captures: &Captures| {
+            match self.values.get(&captures[1]) {
+                Some(value) => value.clone(),
+                None => {
+                    unknown.get_or_insert_with(|| captures[0].to_string());
+                    captures[0].to_string()
+                }
+            }
+        });
+        match unknown {
+            None => Ok(expanded.into_owned()),
+            Some(name) => Err(anyhow!("Unknown path macro {} in {}", name, path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn macros() -> PathMacros {
+        PathMacros::builtin("/home/foo", "/home/foo/.config/JetBrains/IdeaIC2021.1")
+    }
+
+    #[test]
+    fn expand_builtin_macros() {
+        let macros = macros();
+        assert_eq!(
+            macros.expand("$USER_HOME$/dev/mdcat").unwrap(),
+            "/home/foo/dev/mdcat"
+        );
+        assert_eq!(
+            macros.expand("$APPLICATION_CONFIG_DIR$/scratches").unwrap(),
+            "/home/foo/.config/JetBrains/IdeaIC2021.1/scratches"
+        );
+        assert_eq!(macros.expand("/srv/mdcat").unwrap(), "/srv/mdcat");
+        assert_eq!(macros.expand("/srv/$/mdcat").unwrap(), "/srv/$/mdcat");
+    }
+
+    #[test]
+    fn reject_unknown_macros() {
+        let macros = macros();
+        for path in &["$PROJECT_DIR$/mdcat", "$USER_HOME$/$FOO$/mdcat"] {
+            assert!(macros.expand(path).is_err(), "Not rejected: {}", path);
+        }
+    }
+
+    #[test]
+    fn expand_custom_macros() {
+        let mut macros = macros();
+        let xml = r#"<application>
+  <component name="PathMacrosImpl">
+    <macro name="CODE" value="/srv/code" />
+    <macro name="WORK" value="$USER_HOME$/work" />
+    <macro name="USER_HOME" value="/tmp" />
+    <macro name="BROKEN" />
+    <macro name="UNKNOWN" value="$APPLICATION_HOME_DIR$/plugins" />
+  </component>
+</application>"#;
+        macros.add_custom(xml.as_bytes()).unwrap();
+        assert_eq!(macros.expand("$CODE$/mdcat").unwrap(), "/srv/code/mdcat");
+        assert_eq!(
+            macros.expand("$WORK$/mdcat").unwrap(),
+            "/home/foo/work/mdcat"
+        );
+        assert_eq!(
+            macros.expand("$USER_HOME$/mdcat").unwrap(),
+            "/home/foo/mdcat"
+        );
+        assert!(macros.expand("$BROKEN$/mdcat").is_err());
+        assert!(macros.expand("$UNKNOWN$/mdcat").is_err());
+    }
+}
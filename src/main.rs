@@ -8,16 +8,15 @@
 
 //! Gnome search provider for Jetbrains products
 
+use std::cell::RefCell;
 use std::ffi::OsStr;
-use std::fs::File;
-use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::str::FromStr;
 
 use anyhow::{Context, Result};
-use elementtree::Element;
 use lazy_static::lazy_static;
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 use regex::Regex;
 use std::convert::TryFrom;
 
@@ -27,9 +26,26 @@ use gnome_search_provider_common::export::gio;
 use gnome_search_provider_common::export::glib;
 use gnome_search_provider_common::export::zbus;
 use gnome_search_provider_common::export::zbus::export::names::WellKnownName;
+use gnome_search_provider_common::history::History;
 use gnome_search_provider_common::log::*;
 use gnome_search_provider_common::mainloop::*;
-use gnome_search_provider_common::matching::*;
+
+use crate::config::Config;
+use crate::debug::DebugInterface;
+use crate::installed::{load_provider_files, search_provider_dirs};
+use crate::source::{AllProjectsSource, JetbrainsProjectsSource, LOAD_TIMEOUT};
+
+mod config;
+mod debug;
+mod description;
+mod doctor;
+mod installed;
+mod macros;
+mod missing;
+mod names;
+mod recent;
+mod search;
+mod source;
 
 /// A path with an associated version.
 #[derive(Debug)]
@@ -39,42 +55,6 @@ struct VersionedPath {
     version: (u16, u16),
 }
 
-/// Read paths of all recent projects from the given `reader`.
-fn read_recent_jetbrains_projects<R: Read>(reader: R) -> Result<Vec<String>> {
-    let element = Element::from_reader(reader)?;
-    let home = dirs::home_dir()
-        .with_context(|| "$HOME directory required")?
-        .into_os_string()
-        .into_string()
-        .ok()
-        .with_context(|| "$HOME not a valid UTF-8 string")?;
-
-    trace!("Finding projects in {:?}", element);
-
-    let projects = element
-        .find_all("component")
-        .find(|e| {
-            e.get_attr("name") == Some("RecentProjectsManager")
-                || e.get_attr("name") == Some("RiderRecentProjectsManager")
-        })
-        .and_then(|comp| {
-            comp.find_all("option")
-                .find(|e| e.get_attr("name") == Some("additionalInfo"))
-        })
-        .and_then(|opt| opt.find("map"))
-        .map(|map| {
-            map.find_all("entry")
-                .filter_map(|entry| entry.get_attr("key"))
-                .map(|key| key.replace("$USER_HOME$", &home))
-                .collect()
-        })
-        .unwrap_or_default();
-
-    trace!("Parsed projects {:?} from {:?}", projects, element);
-
-    Ok(projects)
-}
-
 impl VersionedPath {
     /// Extract the version number from the given path.
     ///
@@ -108,9 +88,35 @@ impl VersionedPath {
     }
 }
 
+/// The directory which contains vendor configuration directories.
+#[derive(Debug, Clone, Copy)]
+enum ConfigHome<'a> {
+    /// The configuration directory of the user, i.e. `$XDG_CONFIG_HOME`.
+    ///
+    /// This is used by toolbox, tarball and snap installations.
+    User,
+    /// The configuration directory of the flatpak app with the given ID.
+    ///
+    /// Flatpak apps keep their configuration in a sandbox, at `~/.var/app/<id>/config`.
+    Flatpak(&'a str),
+}
+
+impl ConfigHome<'_> {
+    /// Get the path of this configuration directory.
+    fn path(&self) -> Option<PathBuf> {
+        match self {
+            ConfigHome::User => dirs::config_dir(),
+            ConfigHome::Flatpak(app_id) => dirs::home_dir()
+                .map(|home| home.join(".var").join("app").join(app_id).join("config")),
+        }
+    }
+}
+
 /// A location for configuration of a Jetbrains product.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 struct ConfigLocation<'a> {
+    /// The directory which contains the vendor configuration directory.
+    config_home: ConfigHome<'a>,
     /// The vendor configuration directory.
     vendor_dir: &'a str,
     /// A glob for configuration directories inside the vendor directory.
@@ -120,64 +126,230 @@ struct ConfigLocation<'a> {
 }
 
 impl ConfigLocation<'_> {
-    /// Find the configuration directory of the latest installed product version.
-    fn find_config_dir_of_latest_version(&self, config_home: &Path) -> Option<VersionedPath> {
-        let vendor_dir = config_home.join(self.vendor_dir);
-        let dir = globwalk::GlobWalkerBuilder::new(vendor_dir, self.config_glob)
-            .build()
-            .expect("Failed to build glob pattern")
-            .filter_map(Result::ok)
-            .map(globwalk::DirEntry::into_path)
-            .filter_map(VersionedPath::extract_version)
-            .max_by_key(|p| p.version);
-        debug!("Found config dir {:?} in {}", dir, config_home.display());
-        dir
+    /// Get the location of recent projects files of the given `versions` in `config_home`.
+    fn projects_location(
+        &self,
+        config_home: &Path,
+        versions: VersionSelection,
+    ) -> ProjectsLocation {
+        ProjectsLocation {
+            vendor_dir: config_home.join(self.vendor_dir),
+            config_glob: self.config_glob.to_string(),
+            projects_filename: self.projects_filename.to_string(),
+            versions,
+        }
+    }
+
+    /// Find the configuration directories of all installed product versions.
+    ///
+    /// Return directories ordered by version, latest version first.
+    fn find_config_dirs(&self, config_home: &Path) -> Vec<VersionedPath> {
+        self.projects_location(config_home, VersionSelection::All)
+            .find_config_dirs()
+    }
+
+    /// Get the recent projects files of the given `versions`.
+    ///
+    /// Return files ordered by version, latest version first.  The files need not exist.
+    fn recent_projects_files(
+        &self,
+        config_home: &Path,
+        versions: VersionSelection,
+    ) -> Vec<PathBuf> {
+        self.projects_location(config_home, versions)
+            .recent_projects_files()
     }
+}
 
-    /// Find the latest recent projects file.
-    fn find_latest_recent_projects_file(&self, config_home: &Path) -> Option<PathBuf> {
-        let file = self
-            .find_config_dir_of_latest_version(config_home)
-            .map(|p| p.into_path())
-            .map(|p| p.join("options").join(self.projects_filename))
-            .filter(|p| p.is_file());
+/// Where to find recent projects files of a product.
+///
+/// Owns everything it needs, so that it can find recent projects files on a worker thread.
+#[derive(Debug, Clone)]
+struct ProjectsLocation {
+    /// The vendor configuration directory.
+    vendor_dir: PathBuf,
+    /// A glob for configuration directories inside the vendor directory.
+    config_glob: String,
+    /// The file name for recent projects.
+    projects_filename: String,
+    /// Which product versions to read recent projects from.
+    versions: VersionSelection,
+}
+
+impl ProjectsLocation {
+    /// Find the configuration directories of all installed product versions.
+    ///
+    /// Return directories ordered by version, latest version first.
+    fn find_config_dirs(&self) -> Vec<VersionedPath> {
+        let mut dirs: Vec<VersionedPath> =
+            globwalk::GlobWalkerBuilder::new(&self.vendor_dir, &self.config_glob)
+                .build()
+                .expect("Failed to build glob pattern")
+                .filter_map(Result::ok)
+                .map(globwalk::DirEntry::into_path)
+                .filter_map(VersionedPath::extract_version)
+                .collect();
+        dirs.sort_by_key(|dir| std::cmp::Reverse(dir.version));
+        debug!(
+            "Found config dirs {:?} in {}",
+            dirs,
+            self.vendor_dir.display()
+        );
+        dirs
+    }
+
+    /// Get the recent projects file in the given configuration directory.
+    fn recent_projects_file(&self, config_dir: VersionedPath) -> PathBuf {
+        config_dir
+            .into_path()
+            .join("options")
+            .join(&self.projects_filename)
+    }
+
+    /// Get the recent projects files of the selected versions.
+    ///
+    /// Return files ordered by version, latest version first.  The files need not exist.
+    fn recent_projects_files(&self) -> Vec<PathBuf> {
+        let dirs = self.find_config_dirs();
+        let count = match self.versions {
+            VersionSelection::Latest => 1,
+            VersionSelection::All => dirs.len(),
+        };
+        let files: Vec<PathBuf> = dirs
+            .into_iter()
+            .take(count)
+            .map(|dir| self.recent_projects_file(dir))
+            .collect();
         debug!(
-            "Found recent projects file {:?} in {}",
-            file,
-            config_home.display()
+            "Found recent projects files {:?} in {}",
+            files,
+            self.vendor_dir.display()
+        );
+        files
+    }
+}
+
+/// A project on a remote host, accessed via SSH.
+#[derive(Debug, PartialEq)]
+struct SshProject<'a> {
+    /// The user to login as, if any.
+    user: Option<&'a str>,
+    /// The remote host.
+    host: &'a str,
+    /// The SSH port, if not the default port.
+    port: Option<u16>,
+    /// The path of the project on the remote host.
+    path: &'a str,
+}
+
+impl SshProject<'_> {
+    /// Get the URI to open this project with Jetbrains Gateway.
+    fn gateway_uri(&self) -> String {
+        let escape = |s: &str| glib::uri_escape_string(s, None, true);
+        let mut uri = format!(
+            "jetbrains-gateway://connect#type=ssh&deploy=false&host={}&port={}",
+            escape(self.host),
+            self.port.unwrap_or(22)
         );
-        file
+        if let Some(user) = self.user {
+            uri.push_str(&format!("&user={}", escape(user)));
+        }
+        uri.push_str(&format!("&projectPath={}", escape(self.path)));
+        uri
     }
 }
 
-/// Get the name of the Jetbrains product at the given path.
+/// The location of a recent project.
+#[derive(Debug, PartialEq)]
+enum ProjectLocation<'a> {
+    /// A project at the given path on the local file system.
+    Local(&'a str),
+    /// A project on a remote host, accessed via SSH.
+    Ssh(SshProject<'a>),
+    /// A remote project which we cannot open, e.g. in a WSL distribution.
+    Unsupported,
+}
+
+/// Parse the location of a recent project from its `path`.
 ///
-/// Look for a `name` file in the `.idea` sub-directory; if that file does not exist
-/// or cannot be read take the file name of `path`, and ultimately return `None` if
-/// the name cannot be determined.
-fn get_project_name<P: AsRef<Path>>(path: P) -> Option<String> {
-    let name_file = path.as_ref().join(".idea").join(".name");
-    trace!("Trying to read name from {}", name_file.display());
-    File::open(&name_file)
-        .and_then(|mut source| {
-            let mut buffer = String::new();
-            source.read_to_string(&mut buffer)?;
-            trace!("Read project name {} from {}", buffer, name_file.display());
-            Ok(buffer)
-        })
-        .ok()
-        .or_else(|| {
-            trace!(
-                "Falling back to file name of {} as project name",
-                path.as_ref().display()
-            );
-            path.as_ref()
-                .file_name()
-                .map(|name| name.to_string_lossy().to_string())
-        })
+/// Remote projects have an `ssh://[user@]host[:port]/path` URL as path; projects in a WSL
+/// distribution have a UNC path starting with `//wsl$/` or `//wsl.localhost/`.
+fn parse_project_location(path: &str) -> ProjectLocation<'_> {
+    if let Some(url) = path.strip_prefix("ssh://") {
+        let ssh = url.find('/').and_then(|index| {
+            let (authority, path) = url.split_at(index);
+            let (user, host_and_port) = match authority.rsplit_once('@') {
+                Some((user, host_and_port)) => (Some(user), host_and_port),
+                None => (None, authority),
+            };
+            let (host, port) = match host_and_port.rsplit_once(':') {
+                Some((host, port)) => match u16::from_str(port) {
+                    Ok(port) => (host, Some(port)),
+                    Err(_) => (host_and_port, None),
+                },
+                None => (host_and_port, None),
+            };
+            Some(SshProject {
+                user,
+                host,
+                port,
+                path,
+            })
+            .filter(|ssh| !ssh.host.is_empty())
+        });
+        ssh.map_or(ProjectLocation::Unsupported, ProjectLocation::Ssh)
+    } else if [
+        "//wsl$/",
+        "//wsl.localhost/",
+        "\\\\wsl$\\",
+        "\\\\wsl.localhost\\",
+    ]
+    .iter()
+    .any(|prefix| path.starts_with(prefix))
+        || path.contains("://")
+    {
+        ProjectLocation::Unsupported
+    } else {
+        ProjectLocation::Local(path)
+    }
+}
+
+/// Whether the local project at `path` is a Visual Studio solution.
+///
+/// Rider records solution files, not directories, as recent solutions.
+fn is_solution(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "sln")
+}
+
+/// Get the directory of the local project at `path`.
+///
+/// This is the directory which contains the solution for Rider solutions, and `path` itself
+/// for all other projects.
+fn project_directory(path: &Path) -> &Path {
+    if is_solution(path) {
+        path.parent().unwrap_or(path)
+    } else {
+        path
+    }
+}
+
+/// Find the icon of the Jetbrains project at the given path.
+///
+/// Jetbrains IDEs show `.idea/icon.svg` as project icon, or `.idea/icon_dark.svg` in a dark theme.
+/// Prefer the dark icon if it exists, because the Gnome Shell overview has a dark background;
+/// return `None` if the project has no icon.
+fn find_project_icon<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+    let idea_dir = path.as_ref().join(".idea");
+    let icon = ["icon_dark.svg", "icon.svg"]
+        .iter()
+        .map(|name| idea_dir.join(name))
+        .find(|icon| icon.is_file());
+    trace!("Found icon {:?} for {}", icon, path.as_ref().display());
+    icon
 }
 
 /// A search provider to expose from this service.
+#[derive(Debug, Clone, Copy)]
 struct ProviderDefinition<'a> {
     /// A human readable label for this provider.
     label: &'a str,
@@ -211,6 +383,7 @@ const PROVIDERS: &[ProviderDefinition] = &[
         desktop_id: "jetbrains-clion.desktop",
         relative_obj_path: "toolbox/clion",
         config: ConfigLocation {
+            config_home: ConfigHome::User,
             vendor_dir: "JetBrains",
             config_glob: "CLion*",
             projects_filename: "recentProjects.xml",
@@ -221,6 +394,7 @@ const PROVIDERS: &[ProviderDefinition] = &[
         desktop_id: "jetbrains-goland.desktop",
         relative_obj_path: "toolbox/goland",
         config: ConfigLocation {
+            config_home: ConfigHome::User,
             vendor_dir: "JetBrains",
             config_glob: "GoLand*",
             projects_filename: "recentProjects.xml",
@@ -231,6 +405,7 @@ const PROVIDERS: &[ProviderDefinition] = &[
         desktop_id: "jetbrains-idea.desktop",
         relative_obj_path: "toolbox/idea",
         config: ConfigLocation {
+            config_home: ConfigHome::User,
             vendor_dir: "JetBrains",
             config_glob: "IntelliJIdea*",
             projects_filename: "recentProjects.xml",
@@ -241,6 +416,7 @@ const PROVIDERS: &[ProviderDefinition] = &[
         desktop_id: "jetbrains-idea-ce.desktop",
         relative_obj_path: "toolbox/ideace",
         config: ConfigLocation {
+            config_home: ConfigHome::User,
             vendor_dir: "JetBrains",
             config_glob: "IdeaIC*",
             projects_filename: "recentProjects.xml",
@@ -251,6 +427,7 @@ const PROVIDERS: &[ProviderDefinition] = &[
         desktop_id: "jetbrains-phpstorm.desktop",
         relative_obj_path: "toolbox/phpstorm",
         config: ConfigLocation {
+            config_home: ConfigHome::User,
             vendor_dir: "JetBrains",
             config_glob: "PhpStorm*",
             projects_filename: "recentProjects.xml",
@@ -261,6 +438,7 @@ const PROVIDERS: &[ProviderDefinition] = &[
         desktop_id: "jetbrains-pycharm.desktop",
         relative_obj_path: "toolbox/pycharm",
         config: ConfigLocation {
+            config_home: ConfigHome::User,
             vendor_dir: "JetBrains",
             config_glob: "PyCharm*",
             projects_filename: "recentProjects.xml",
@@ -271,6 +449,7 @@ const PROVIDERS: &[ProviderDefinition] = &[
         desktop_id: "jetbrains-rider.desktop",
         relative_obj_path: "toolbox/rider",
         config: ConfigLocation {
+            config_home: ConfigHome::User,
             vendor_dir: "JetBrains",
             config_glob: "Rider*",
             projects_filename: "recentSolutions.xml",
@@ -281,6 +460,7 @@ const PROVIDERS: &[ProviderDefinition] = &[
         desktop_id: "jetbrains-rubymine.desktop",
         relative_obj_path: "toolbox/rubymine",
         config: ConfigLocation {
+            config_home: ConfigHome::User,
             vendor_dir: "JetBrains",
             config_glob: "RubyMine*",
             projects_filename: "recentProjects.xml",
@@ -291,6 +471,7 @@ const PROVIDERS: &[ProviderDefinition] = &[
         desktop_id: "jetbrains-studio.desktop",
         relative_obj_path: "toolbox/studio",
         config: ConfigLocation {
+            config_home: ConfigHome::User,
             vendor_dir: "Google",
             config_glob: "AndroidStudio*",
             projects_filename: "recentProjects.xml",
@@ -301,6 +482,260 @@ const PROVIDERS: &[ProviderDefinition] = &[
         desktop_id: "jetbrains-webstorm.desktop",
         relative_obj_path: "toolbox/webstorm",
         config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "JetBrains",
+            config_glob: "WebStorm*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "Gateway (toolbox)",
+        desktop_id: "jetbrains-gateway.desktop",
+        relative_obj_path: "toolbox/gateway",
+        config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "JetBrains",
+            config_glob: "JetBrainsGateway*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "CLion (snap)",
+        desktop_id: "clion_clion.desktop",
+        relative_obj_path: "snap/clion",
+        config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "JetBrains",
+            config_glob: "CLion*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "GoLand (snap)",
+        desktop_id: "goland_goland.desktop",
+        relative_obj_path: "snap/goland",
+        config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "JetBrains",
+            config_glob: "GoLand*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "IDEA (snap)",
+        desktop_id: "intellij-idea-ultimate_intellij-idea-ultimate.desktop",
+        relative_obj_path: "snap/idea",
+        config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "JetBrains",
+            config_glob: "IntelliJIdea*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "IDEA Community Edition (snap)",
+        desktop_id: "intellij-idea-community_intellij-idea-community.desktop",
+        relative_obj_path: "snap/ideace",
+        config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "JetBrains",
+            config_glob: "IdeaIC*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "PHPStorm (snap)",
+        desktop_id: "phpstorm_phpstorm.desktop",
+        relative_obj_path: "snap/phpstorm",
+        config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "JetBrains",
+            config_glob: "PhpStorm*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "PyCharm (snap)",
+        desktop_id: "pycharm-professional_pycharm-professional.desktop",
+        relative_obj_path: "snap/pycharm",
+        config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "JetBrains",
+            config_glob: "PyCharm2*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "PyCharm Community Edition (snap)",
+        desktop_id: "pycharm-community_pycharm-community.desktop",
+        relative_obj_path: "snap/pycharmce",
+        config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "JetBrains",
+            config_glob: "PyCharmCE*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "Rider (snap)",
+        desktop_id: "rider_rider.desktop",
+        relative_obj_path: "snap/rider",
+        config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "JetBrains",
+            config_glob: "Rider*",
+            projects_filename: "recentSolutions.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "RubyMine (snap)",
+        desktop_id: "rubymine_rubymine.desktop",
+        relative_obj_path: "snap/rubymine",
+        config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "JetBrains",
+            config_glob: "RubyMine*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "Android Studio (snap)",
+        desktop_id: "android-studio_android-studio.desktop",
+        relative_obj_path: "snap/studio",
+        config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "Google",
+            config_glob: "AndroidStudio*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "WebStorm (snap)",
+        desktop_id: "webstorm_webstorm.desktop",
+        relative_obj_path: "snap/webstorm",
+        config: ConfigLocation {
+            config_home: ConfigHome::User,
+            vendor_dir: "JetBrains",
+            config_glob: "WebStorm*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "CLion (flatpak)",
+        desktop_id: "com.jetbrains.CLion.desktop",
+        relative_obj_path: "flatpak/clion",
+        config: ConfigLocation {
+            config_home: ConfigHome::Flatpak("com.jetbrains.CLion"),
+            vendor_dir: "JetBrains",
+            config_glob: "CLion*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "GoLand (flatpak)",
+        desktop_id: "com.jetbrains.GoLand.desktop",
+        relative_obj_path: "flatpak/goland",
+        config: ConfigLocation {
+            config_home: ConfigHome::Flatpak("com.jetbrains.GoLand"),
+            vendor_dir: "JetBrains",
+            config_glob: "GoLand*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "IDEA (flatpak)",
+        desktop_id: "com.jetbrains.IntelliJ-IDEA-Ultimate.desktop",
+        relative_obj_path: "flatpak/idea",
+        config: ConfigLocation {
+            config_home: ConfigHome::Flatpak("com.jetbrains.IntelliJ-IDEA-Ultimate"),
+            vendor_dir: "JetBrains",
+            config_glob: "IntelliJIdea*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "IDEA Community Edition (flatpak)",
+        desktop_id: "com.jetbrains.IntelliJ-IDEA-Community.desktop",
+        relative_obj_path: "flatpak/ideace",
+        config: ConfigLocation {
+            config_home: ConfigHome::Flatpak("com.jetbrains.IntelliJ-IDEA-Community"),
+            vendor_dir: "JetBrains",
+            config_glob: "IdeaIC*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "PHPStorm (flatpak)",
+        desktop_id: "com.jetbrains.PhpStorm.desktop",
+        relative_obj_path: "flatpak/phpstorm",
+        config: ConfigLocation {
+            config_home: ConfigHome::Flatpak("com.jetbrains.PhpStorm"),
+            vendor_dir: "JetBrains",
+            config_glob: "PhpStorm*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "PyCharm (flatpak)",
+        desktop_id: "com.jetbrains.PyCharm-Professional.desktop",
+        relative_obj_path: "flatpak/pycharm",
+        config: ConfigLocation {
+            config_home: ConfigHome::Flatpak("com.jetbrains.PyCharm-Professional"),
+            vendor_dir: "JetBrains",
+            config_glob: "PyCharm2*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "PyCharm Community Edition (flatpak)",
+        desktop_id: "com.jetbrains.PyCharm-Community.desktop",
+        relative_obj_path: "flatpak/pycharmce",
+        config: ConfigLocation {
+            config_home: ConfigHome::Flatpak("com.jetbrains.PyCharm-Community"),
+            vendor_dir: "JetBrains",
+            config_glob: "PyCharmCE*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "Rider (flatpak)",
+        desktop_id: "com.jetbrains.Rider.desktop",
+        relative_obj_path: "flatpak/rider",
+        config: ConfigLocation {
+            config_home: ConfigHome::Flatpak("com.jetbrains.Rider"),
+            vendor_dir: "JetBrains",
+            config_glob: "Rider*",
+            projects_filename: "recentSolutions.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "RubyMine (flatpak)",
+        desktop_id: "com.jetbrains.RubyMine.desktop",
+        relative_obj_path: "flatpak/rubymine",
+        config: ConfigLocation {
+            config_home: ConfigHome::Flatpak("com.jetbrains.RubyMine"),
+            vendor_dir: "JetBrains",
+            config_glob: "RubyMine*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "Android Studio (flatpak)",
+        desktop_id: "com.google.AndroidStudio.desktop",
+        relative_obj_path: "flatpak/studio",
+        config: ConfigLocation {
+            config_home: ConfigHome::Flatpak("com.google.AndroidStudio"),
+            vendor_dir: "Google",
+            config_glob: "AndroidStudio*",
+            projects_filename: "recentProjects.xml",
+        },
+    },
+    ProviderDefinition {
+        label: "WebStorm (flatpak)",
+        desktop_id: "com.jetbrains.WebStorm.desktop",
+        relative_obj_path: "flatpak/webstorm",
+        config: ConfigLocation {
+            config_home: ConfigHome::Flatpak("com.jetbrains.WebStorm"),
             vendor_dir: "JetBrains",
             config_glob: "WebStorm*",
             projects_filename: "recentProjects.xml",
@@ -308,47 +743,59 @@ const PROVIDERS: &[ProviderDefinition] = &[
     },
 ];
 
-struct JetbrainsProjectsSource<'a> {
-    app_id: String,
-    /// Where to look for the configuration and the list of recent projects.
-    config: &'a ConfigLocation<'a>,
+/// Which product versions to read recent projects from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum VersionSelection {
+    /// Only read recent projects of the latest installed version.
+    Latest,
+    /// Read and merge recent projects of all installed versions.
+    All,
 }
 
-impl<'a> ItemsSource<AppLaunchItem> for JetbrainsProjectsSource<'a> {
-    type Err = anyhow::Error;
-
-    fn find_recent_items(&self) -> Result<IdMap<AppLaunchItem>, Self::Err> {
-        info!("Searching recent projects for {}", self.app_id);
-        let mut items = IndexMap::new();
-        let config_home = dirs::config_dir().unwrap();
-        if let Some(projects_file) = self.config.find_latest_recent_projects_file(&config_home) {
-            for path in read_recent_jetbrains_projects(File::open(projects_file)?)? {
-                if let Some(name) = get_project_name(&path) {
-                    trace!("Found project {} at {} for {}", name, path, self.app_id);
-                    let id = format!("jetbrains-recent-project-{}-{}", self.app_id, path);
-                    items.insert(
-                        id,
-                        AppLaunchItem {
-                            name,
-                            target: AppLaunchTarget::File(path),
-                        },
-                    );
-                } else {
-                    trace!("Skipping {}, failed to determine project name", path);
-                }
-            }
-        };
-        info!("Found {} project(s) for {}", items.len(), self.app_id,);
-        Ok(items)
+/// The default desktop ID of the app to show recent projects of all IDEs for.
+const ALL_PROJECTS_DESKTOP_ID: &str = "jetbrains-toolbox.desktop";
+
+/// The object path of the search provider for recent projects of all IDEs.
+const ALL_PROJECTS_OBJ_PATH: &str = "/de/swsnr/searchprovider/jetbrains/all";
+
+/// Get the desktop ID of the app to show recent projects of all IDEs for.
+///
+/// Use the desktop ID from the installed search provider file for all projects, so that
+/// the provider can show under any app, and fall back to the JetBrains Toolbox.
+fn all_projects_desktop_id() -> String {
+    let (files, errors) = load_provider_files(&search_provider_dirs());
+    for error in errors {
+        debug!("Skipping search provider file: {:#}", error);
     }
+    files
+        .into_iter()
+        .find(|file| file.object_path == ALL_PROJECTS_OBJ_PATH)
+        .map_or_else(
+            || ALL_PROJECTS_DESKTOP_ID.to_string(),
+            |file| file.desktop_id,
+        )
 }
 
 /// The name to request on the bus.
 const BUSNAME: &str = "de.swsnr.searchprovider.Jetbrains";
 
+/// Get the file to store the history of activated results in.
+///
+/// Use `$XDG_STATE_HOME`, and fall back to `~/.local/state` if it's unset or not absolute.
+fn history_file() -> Option<PathBuf> {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("state")))?;
+    Some(state_home.join(env!("CARGO_PKG_NAME")).join("history.json"))
+}
+
 fn register_search_providers(
     connection: &zbus::Connection,
     object_server: &mut zbus::ObjectServer,
+    config: &'static Config,
+    versions: VersionSelection,
+    history: Rc<RefCell<History>>,
 ) -> Result<()> {
     let launch_context = create_launch_context(
         connection.clone(),
@@ -358,23 +805,73 @@ fn register_search_providers(
             documentation: vec![env!("CARGO_PKG_HOMEPAGE").to_string()],
         },
     );
-    for provider in PROVIDERS {
+    let mut all_sources = Vec::new();
+    for provider in config.providers() {
         if let Some(app) = gio::DesktopAppInfo::new(provider.desktop_id) {
             info!(
                 "Registering provider for {} at {}",
                 provider.desktop_id,
                 provider.objpath()
             );
+            let source = Rc::new(JetbrainsProjectsSource::new(
+                provider.desktop_id.to_string(),
+                provider.config,
+                provider.config.config_home.path(),
+                versions,
+                config.missing_projects(&provider),
+                config.description(&provider),
+                Some(LOAD_TIMEOUT),
+            ));
+            all_sources.push((app.clone(), source.clone()));
             let dbus_provider = AppItemSearchProvider::new(
                 app,
-                JetbrainsProjectsSource {
-                    app_id: provider.desktop_id.to_string(),
-                    config: &provider.config,
-                },
+                source.clone(),
                 launch_context.clone(),
+                config.match_mode(&provider),
+                config.launch_search(&provider),
+                config.result_limits(&provider),
+                history.clone(),
             );
             object_server.at(provider.objpath().as_str(), dbus_provider)?;
+            let debug = DebugInterface::new(
+                provider.desktop_id.to_string(),
+                source.clone(),
+                vec![source],
+            );
+            object_server.at(provider.objpath().as_str(), debug)?;
+        }
+    }
+    let all_projects_desktop_id = all_projects_desktop_id();
+    match gio::DesktopAppInfo::new(&all_projects_desktop_id) {
+        Some(app) => {
+            info!(
+                "Registering provider for all projects of {} at {}",
+                all_projects_desktop_id, ALL_PROJECTS_OBJ_PATH
+            );
+            let sources = all_sources
+                .iter()
+                .map(|(_, source)| source.clone())
+                .collect();
+            let source = Rc::new(AllProjectsSource::new(all_sources));
+            let dbus_provider = AppItemSearchProvider::new(
+                app,
+                source.clone(),
+                launch_context,
+                config.default_match_mode(),
+                config.default_launch_search(),
+                config.default_result_limits(),
+                history,
+            );
+            object_server.at(ALL_PROJECTS_OBJ_PATH, dbus_provider)?;
+            object_server.at(
+                ALL_PROJECTS_OBJ_PATH,
+                DebugInterface::new("all projects".to_string(), source, sources),
+            )?;
         }
+        None => info!(
+            "{} not found, not registering provider for all projects",
+            all_projects_desktop_id
+        ),
     }
     Ok(())
 }
@@ -382,11 +879,12 @@ fn register_search_providers(
 /// Starts the DBUS service.
 ///
 /// Connect to the session bus and register a new DBus object for every provider
-/// whose underlying app is installed.
+/// in `config` whose underlying app is installed; providers read recent projects from the
+/// given `versions` of their product.
 ///
 /// Then register the connection on the Glib main loop and install a callback to
 /// handle incoming messages.
-fn start_dbus_service() -> Result<()> {
+fn start_dbus_service(config: &'static Config, versions: VersionSelection) -> Result<()> {
     let mainloop = create_main_loop();
     let context = glib::MainContext::ref_thread_default();
 
@@ -395,7 +893,20 @@ fn start_dbus_service() -> Result<()> {
 
     info!("Registering all search providers");
     let mut object_server = zbus::ObjectServer::new(&connection);
-    register_search_providers(&connection, &mut object_server)?;
+    let history = Rc::new(RefCell::new(match history_file() {
+        Some(file) => History::load(file),
+        None => {
+            warn!("No state directory found, not saving the history of activated results");
+            History::default()
+        }
+    }));
+    register_search_providers(
+        &connection,
+        &mut object_server,
+        config,
+        versions,
+        history.clone(),
+    )?;
 
     info!("All providers registered, acquiring {}", BUSNAME);
     context
@@ -409,6 +920,7 @@ fn start_dbus_service() -> Result<()> {
     context.spawn_local(run_server(connection.inner().clone(), object_server));
 
     mainloop.run();
+    history.borrow_mut().flush();
 
     Ok(())
 }
@@ -429,14 +941,95 @@ Set $RUST_LOG to control the log level",
             Arg::with_name("providers")
                 .long("--providers")
                 .help("List all providers"),
+        )
+        .arg(
+            Arg::with_name("all-versions")
+                .long("--all-versions")
+                .global(true)
+                .help("Search recent projects of all installed versions of each product, not only of the latest"),
+        )
+        .subcommand(
+            SubCommand::with_name("search")
+                .setting(AppSettings::TrailingVarArg)
+                .about("Search recent projects and print results with their scores")
+                .arg(
+                    Arg::with_name("json")
+                        .long("--json")
+                        .help("Print results as JSON"),
+                )
+                .arg(
+                    Arg::with_name("all")
+                        .long("--all")
+                        .help("Print all matching projects with their plain score, without result limits and history"),
+                )
+                .arg(
+                    Arg::with_name("provider")
+                        .long("--provider")
+                        .takes_value(true)
+                        .value_name("PROVIDER")
+                        .help("Only search the provider with the given label or relative object path"),
+                )
+                .arg(
+                    Arg::with_name("terms")
+                        .required(true)
+                        .multiple(true)
+                        .allow_hyphen_values(true)
+                        .help("The terms to search for"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Check the installation of all providers and report problems"),
         );
     let matches = app.get_matches();
+    let versions = |matches: &ArgMatches| {
+        if matches.is_present("all-versions") {
+            VersionSelection::All
+        } else {
+            VersionSelection::Latest
+        }
+    };
     if matches.is_present("providers") {
-        let mut labels: Vec<&'static str> = PROVIDERS.iter().map(|p| p.label).collect();
+        setup_logging_for_cli();
+        let config = Config::load();
+        let mut labels: Vec<&str> = config.providers().map(|p| p.label).collect();
         labels.sort_unstable();
         for label in labels {
             println!("{}", label)
         }
+    } else if let Some(matches) = matches.subcommand_matches("search") {
+        setup_logging_for_cli();
+        let config = Config::load();
+        let terms: Vec<&str> = matches.values_of("terms").unwrap().collect();
+        let history = if matches.is_present("all") {
+            None
+        } else {
+            Some(history_file().map(History::load).unwrap_or_default())
+        };
+        let results = search::search(
+            &config,
+            versions(matches),
+            matches.value_of("provider"),
+            &terms,
+            history.as_ref(),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("Search failed: {:#}", err);
+            std::process::exit(1)
+        });
+        if matches.is_present("json") {
+            println!("{}", serde_json::to_string_pretty(&results).unwrap());
+        } else {
+            for result in results {
+                println!("{}", result.to_tsv());
+            }
+        }
+    } else if let Some(matches) = matches.subcommand_matches("doctor") {
+        setup_logging_for_cli();
+        let config = Config::load();
+        if doctor::doctor(&config, versions(matches)) == doctor::Status::Error {
+            std::process::exit(1)
+        }
     } else {
         setup_logging_for_service(env!("CARGO_PKG_VERSION"));
 
@@ -446,7 +1039,10 @@ Set $RUST_LOG to control the log level",
             env!("CARGO_PKG_VERSION")
         );
 
-        if let Err(err) = start_dbus_service() {
+        // The configuration lives as long as the service, so we can just leak it
+        // to share it with all providers.
+        let config: &'static Config = Box::leak(Box::new(Config::load()));
+        if let Err(err) = start_dbus_service(config, versions(&matches)) {
             error!("Main loop error: {:#}", err);
             std::process::exit(1)
         }
@@ -471,53 +1067,117 @@ mod tests {
     }
 
     #[test]
-    fn read_recent_projects() {
-        let data: &[u8] = include_bytes!("tests/recentProjects.xml");
-        let home = dirs::home_dir().unwrap();
-        let items = read_recent_jetbrains_projects(data).unwrap();
+    fn config_home_flatpak() {
+        let home = dirs::home_dir().expect("Must have homedir for test");
+        assert_eq!(
+            ConfigHome::Flatpak("com.jetbrains.IntelliJ-IDEA-Ultimate").path(),
+            Some(
+                home.join(".var")
+                    .join("app")
+                    .join("com.jetbrains.IntelliJ-IDEA-Ultimate")
+                    .join("config")
+            )
+        );
+    }
 
+    #[test]
+    fn project_location_local() {
         assert_eq!(
-            items,
-            vec![
-                home.join("Code")
-                    .join("gh")
-                    .join("mdcat")
-                    .to_string_lossy()
-                    .to_string(),
-                home.join("Code")
-                    .join("gh")
-                    .join("gnome-search-providers-jetbrains")
-                    .to_string_lossy()
-                    .to_string()
-            ]
-        )
+            parse_project_location("/home/foo/dev/mdcat"),
+            ProjectLocation::Local("/home/foo/dev/mdcat")
+        );
     }
 
     #[test]
-    fn read_recent_solutions() {
-        let data: &[u8] = include_bytes!("tests/recentSolutions.xml");
-        let home = dirs::home_dir().unwrap();
-        let items = read_recent_jetbrains_projects(data).unwrap();
+    fn project_location_ssh() {
+        assert_eq!(
+            parse_project_location("ssh://foo@example.com:2222/home/foo/dev/mdcat"),
+            ProjectLocation::Ssh(SshProject {
+                user: Some("foo"),
+                host: "example.com",
+                port: Some(2222),
+                path: "/home/foo/dev/mdcat"
+            })
+        );
+        assert_eq!(
+            parse_project_location("ssh://example.com/srv/mdcat"),
+            ProjectLocation::Ssh(SshProject {
+                user: None,
+                host: "example.com",
+                port: None,
+                path: "/srv/mdcat"
+            })
+        );
+        assert_eq!(
+            parse_project_location("ssh://example.com"),
+            ProjectLocation::Unsupported
+        );
+    }
 
+    #[test]
+    fn project_location_unsupported() {
+        for path in &[
+            "//wsl$/Ubuntu/home/foo/dev/mdcat",
+            "//wsl.localhost/Ubuntu/home/foo/dev/mdcat",
+            "\\\\wsl$\\Ubuntu\\home\\foo\\dev\\mdcat",
+            "https://example.com/mdcat",
+        ] {
+            assert_eq!(parse_project_location(path), ProjectLocation::Unsupported);
+        }
+    }
+
+    #[test]
+    fn ssh_project_gateway_uri() {
+        let ssh = SshProject {
+            user: Some("foo"),
+            host: "example.com",
+            port: None,
+            path: "/home/foo/dev/my project",
+        };
         assert_eq!(
-            items,
-            vec![
-                home.join("Code")
-                    .join("gh")
-                    .join("mdcat")
-                    .to_string_lossy()
-                    .to_string(),
-                home.join("Code")
-                    .join("gh")
-                    .join("gnome-search-providers-jetbrains")
-                    .to_string_lossy()
-                    .to_string()
-            ]
-        )
+            ssh.gateway_uri(),
+            "jetbrains-gateway://connect#type=ssh&deploy=false&host=example.com&port=22&user=foo&projectPath=%2Fhome%2Ffoo%2Fdev%2Fmy%20project"
+        );
+    }
+
+    #[test]
+    fn project_icon() {
+        let projects = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join("tests")
+            .join("projects");
+        assert_eq!(find_project_icon(projects.join("no-icon")), None);
+        assert_eq!(
+            find_project_icon(projects.join("icon")),
+            Some(projects.join("icon").join(".idea").join("icon.svg"))
+        );
+        assert_eq!(
+            find_project_icon(projects.join("dark-icon")),
+            Some(
+                projects
+                    .join("dark-icon")
+                    .join(".idea")
+                    .join("icon_dark.svg")
+            )
+        );
+    }
+
+    #[test]
+    fn solution_directory() {
+        assert!(is_solution(Path::new("/home/foo/dev/Foo/Foo.sln")));
+        assert!(!is_solution(Path::new("/home/foo/dev/mdcat")));
+        assert_eq!(
+            project_directory(Path::new("/home/foo/dev/Foo/Foo.sln")),
+            Path::new("/home/foo/dev/Foo")
+        );
+        assert_eq!(
+            project_directory(Path::new("/home/foo/dev/mdcat")),
+            Path::new("/home/foo/dev/mdcat")
+        );
     }
 
     mod providers {
-        use crate::{BUSNAME, PROVIDERS};
+        use crate::{ALL_PROJECTS_DESKTOP_ID, ALL_PROJECTS_OBJ_PATH, BUSNAME, PROVIDERS};
         use anyhow::{Context, Result};
         use ini::Ini;
         use std::collections::HashSet;
@@ -530,41 +1190,41 @@ mod tests {
             version: String,
         }
 
+        fn load_provider_file(filepath: &Path) -> Result<ProviderFile> {
+            let ini = Ini::load_from_file(filepath)
+                .with_context(|| format!("Failed to parse ini file at {}", filepath.display()))?;
+            let provider = ProviderFile {
+                desktop_id: ini
+                    .get_from(Some("Shell Search Provider"), "DesktopId")
+                    .with_context(|| format!("DesktopId missing in {}", &filepath.display()))?
+                    .to_string(),
+                object_path: ini
+                    .get_from(Some("Shell Search Provider"), "ObjectPath")
+                    .with_context(|| format!("ObjectPath missing in {}", &filepath.display()))?
+                    .to_string(),
+                bus_name: ini
+                    .get_from(Some("Shell Search Provider"), "BusName")
+                    .with_context(|| format!("BusName missing in {}", &filepath.display()))?
+                    .to_string(),
+                version: ini
+                    .get_from(Some("Shell Search Provider"), "Version")
+                    .with_context(|| format!("Version missing in {}", &filepath.display()))?
+                    .to_string(),
+            };
+            Ok(provider)
+        }
+
         fn load_all_provider_files() -> Result<Vec<ProviderFile>> {
-            let mut providers = Vec::new();
             let ini_files = globwalk::GlobWalkerBuilder::new(
                 Path::new(env!("CARGO_MANIFEST_DIR")).join("providers"),
                 "*.ini",
             )
+            .max_depth(1)
             .build()
             .unwrap();
-            for entry in ini_files {
-                let filepath = entry.unwrap().into_path();
-                let ini = Ini::load_from_file(&filepath).with_context(|| {
-                    format!("Failed to parse ini file at {}", filepath.display())
-                })?;
-                let provider = ProviderFile {
-                    desktop_id: ini
-                        .get_from(Some("Shell Search Provider"), "DesktopId")
-                        .with_context(|| format!("DesktopId missing in {}", &filepath.display()))?
-                        .to_string(),
-                    object_path: ini
-                        .get_from(Some("Shell Search Provider"), "ObjectPath")
-                        .with_context(|| format!("ObjectPath missing in {}", &filepath.display()))?
-                        .to_string(),
-                    bus_name: ini
-                        .get_from(Some("Shell Search Provider"), "BusName")
-                        .with_context(|| format!("BusName missing in {}", &filepath.display()))?
-                        .to_string(),
-                    version: ini
-                        .get_from(Some("Shell Search Provider"), "Version")
-                        .with_context(|| format!("Version missing in {}", &filepath.display()))?
-                        .to_string(),
-                };
-                providers.push(provider);
-            }
-
-            Ok(providers)
+            ini_files
+                .map(|entry| load_provider_file(entry.unwrap().path()))
+                .collect()
         }
 
         #[test]
@@ -587,6 +1247,21 @@ mod tests {
             }
         }
 
+        #[test]
+        fn all_projects_provider_has_a_correct_ini_file() {
+            let provider_file = load_provider_file(
+                &Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("providers")
+                    .join("optional")
+                    .join("de.swsnr.searchprovider.jetbrains.all.ini"),
+            )
+            .unwrap();
+            assert_eq!(provider_file.desktop_id, ALL_PROJECTS_DESKTOP_ID);
+            assert_eq!(provider_file.object_path, ALL_PROJECTS_OBJ_PATH);
+            assert_eq!(provider_file.bus_name, BUSNAME);
+            assert_eq!(provider_file.version, "2");
+        }
+
         #[test]
         fn no_extra_ini_files_without_providers() {
             let provider_files = load_all_provider_files().unwrap();
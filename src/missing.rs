@@ -0,0 +1,113 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Detect recent projects whose directory no longer exists.
+
+use std::path::{Path, PathBuf};
+
+use gnome_search_provider_common::export::gio;
+use serde::Deserialize;
+
+/// How to handle recent projects whose directory no longer exists.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingProjects {
+    /// Do not show missing projects in search results.
+    #[default]
+    Hide,
+    /// Show missing projects, but mark them as missing in their description.
+    Show,
+}
+
+/// File system types of network file systems.
+const NETWORK_FS_TYPES: &[&str] = &[
+    "9p",
+    "afs",
+    "ceph",
+    "cifs",
+    "davfs",
+    "fuse.davfs2",
+    "fuse.rclone",
+    "fuse.sshfs",
+    "glusterfs",
+    "ncpfs",
+    "nfs",
+    "nfs4",
+    "smb3",
+    "smbfs",
+    "sshfs",
+];
+
+/// A mount point.
+#[derive(Debug)]
+pub struct MountPoint {
+    /// The path the file system is mounted at.
+    pub path: PathBuf,
+    /// Whether the file system is mounted over network or from a removable device.
+    ///
+    /// Accessing such file systems may block for a long time, e.g. if the network share is
+    /// unreachable.
+    pub slow: bool,
+}
+
+impl MountPoint {
+    /// Get all current mount points.
+    ///
+    /// Reads the mount table only, without accessing any mounted file system.
+    pub fn all() -> Vec<MountPoint> {
+        gio::UnixMountEntry::mounts()
+            .0
+            .into_iter()
+            .map(|mount| MountPoint {
+                path: mount.unix_mount_get_mount_path(),
+                slow: NETWORK_FS_TYPES.contains(&mount.fs_type().as_str())
+                    || mount.guess_can_eject(),
+            })
+            .collect()
+    }
+}
+
+/// Whether `path` is on a slow mount point among `mounts`.
+///
+/// Only look at paths, and never access `path` itself.
+pub fn is_on_slow_mount(mounts: &[MountPoint], path: &Path) -> bool {
+    mounts
+        .iter()
+        .filter(|mount| path.starts_with(&mount.path))
+        .max_by_key(|mount| mount.path.components().count())
+        .is_some_and(|mount| mount.slow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slow_mount() {
+        let mounts = vec![
+            MountPoint {
+                path: PathBuf::from("/"),
+                slow: false,
+            },
+            MountPoint {
+                path: PathBuf::from("/mnt/share"),
+                slow: true,
+            },
+            MountPoint {
+                path: PathBuf::from("/mnt/share/local"),
+                slow: false,
+            },
+        ];
+        assert!(!is_on_slow_mount(&mounts, Path::new("/home/foo/dev/mdcat")));
+        assert!(is_on_slow_mount(&mounts, Path::new("/mnt/share/mdcat")));
+        assert!(!is_on_slow_mount(&mounts, Path::new("/mnt/shared/mdcat")));
+        assert!(!is_on_slow_mount(
+            &mounts,
+            Path::new("/mnt/share/local/mdcat")
+        ));
+        assert!(!is_on_slow_mount(&[], Path::new("/mnt/share/mdcat")));
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Resolve the names of projects.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use elementtree::Element;
+use lazy_static::lazy_static;
+use log::trace;
+use regex::Regex;
+
+/// A function which attempts to determine the name of the project at a path.
+pub type NameResolver = fn(&Path) -> Option<String>;
+
+/// All name resolvers, in order of precedence, each with a label for logging.
+///
+/// The name of a project is the name returned by the first resolver which finds a name.
+pub const NAME_RESOLVERS: &[(&str, NameResolver)] = &[
+    ("idea", idea_name),
+    ("gradle", gradle_name),
+    ("maven", maven_name),
+    ("cargo", cargo_name),
+    ("npm", npm_name),
+    ("solution", solution_name),
+    ("git", git_remote_name),
+    ("directory", directory_name),
+];
+
+/// Resolve the name of the project at `path` with the given `resolvers`.
+///
+/// Return the name from the first resolver which finds a name, or `None` if no resolver
+/// finds a name.
+pub fn resolve_project_name(path: &Path, resolvers: &[(&str, NameResolver)]) -> Option<String> {
+    resolvers.iter().find_map(|(label, resolver)| {
+        let name = resolver(path);
+        if let Some(name) = &name {
+            trace!(
+                "Resolved name {} of {} with {}",
+                name,
+                path.display(),
+                label
+            );
+        }
+        name
+    })
+}
+
+/// Get the directory which holds the `.idea` directory of the Rider `solution`.
+///
+/// Rider keeps the `.idea` directory of a solution `Foo.sln` in `.idea/.idea.Foo/` next to
+/// the solution file.
+pub fn solution_idea_root(solution: &Path) -> Option<PathBuf> {
+    let mut name = OsString::from(".idea.");
+    name.push(solution.file_stem()?);
+    Some(solution.parent()?.join(".idea").join(name))
+}
+
+/// Resolve the name of the Rider `solution` file.
+///
+/// Use the name Rider stores for the solution, and fall back to the file name of the
+/// solution without extension.
+pub fn resolve_solution_name(solution: &Path) -> Option<String> {
+    solution_idea_root(solution)
+        .and_then(|root| idea_name(&root))
+        .or_else(|| {
+            solution
+                .file_stem()
+                .and_then(|stem| non_empty(&stem.to_string_lossy()))
+        })
+}
+
+/// Read the file at `path`, or return `None` if the file cannot be read.
+fn read_file(path: &Path) -> Option<String> {
+    trace!("Trying to read name from {}", path.display());
+    std::fs::read_to_string(path).ok()
+}
+
+/// Return the trimmed `name` unless it's empty.
+fn non_empty(name: &str) -> Option<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Read the name the IDE stores in `.idea/.name`.
+fn idea_name(path: &Path) -> Option<String> {
+    read_file(&path.join(".idea").join(".name")).and_then(|name| non_empty(&name))
+}
+
+/// Read the name of the root project from the Gradle settings.
+fn gradle_name(path: &Path) -> Option<String> {
+    read_file(&path.join("settings.gradle.kts"))
+        .or_else(|| read_file(&path.join("settings.gradle")))
+        .and_then(|contents| parse_gradle_settings(&contents))
+}
+
+/// Parse `rootProject.name` from the `contents` of a Gradle settings file.
+fn parse_gradle_settings(contents: &str) -> Option<String> {
+    lazy_static! {
+        static ref ROOT_PROJECT_NAME: Regex =
+            Regex::new(r#"(?m)^\s*rootProject\.name\s*=\s*["']([^"']+)["']"#).unwrap();
+    }
+    ROOT_PROJECT_NAME
+        .captures(contents)
+        .and_then(|captures| non_empty(&captures[1]))
+}
+
+/// Read the artifact ID from the Maven `pom.xml`.
+fn maven_name(path: &Path) -> Option<String> {
+    read_file(&path.join("pom.xml")).and_then(|contents| parse_maven_pom(&contents))
+}
+
+/// Parse the artifact ID of the project from the `contents` of a `pom.xml`.
+fn parse_maven_pom(contents: &str) -> Option<String> {
+    let project = Element::from_reader(contents.as_bytes()).ok()?;
+    project
+        .children()
+        .find(|child| child.tag().name() == "artifactId")
+        .and_then(|artifact_id| non_empty(artifact_id.text()))
+}
+
+/// Read the package name from `Cargo.toml`.
+fn cargo_name(path: &Path) -> Option<String> {
+    read_file(&path.join("Cargo.toml")).and_then(|contents| parse_cargo_manifest(&contents))
+}
+
+/// Parse the package name from the `contents` of a Cargo manifest.
+///
+/// Return `None` for virtual manifests of workspaces which have no package.
+fn parse_cargo_manifest(contents: &str) -> Option<String> {
+    let manifest: toml::Value = toml::from_str(contents).ok()?;
+    manifest
+        .get("package")?
+        .get("name")?
+        .as_str()
+        .and_then(non_empty)
+}
+
+/// Read the package name from `package.json`.
+fn npm_name(path: &Path) -> Option<String> {
+    read_file(&path.join("package.json")).and_then(|contents| parse_package_json(&contents))
+}
+
+/// Parse the package name from the `contents` of a `package.json`.
+fn parse_package_json(contents: &str) -> Option<String> {
+    let package: serde_json::Value = serde_json::from_str(contents).ok()?;
+    package.get("name")?.as_str().and_then(non_empty)
+}
+
+/// Use the name of the single Visual Studio solution in `path`.
+///
+/// Return `None` if `path` contains no or more than one solution.
+fn solution_name(path: &Path) -> Option<String> {
+    let mut solutions = std::fs::read_dir(path)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|file| file.extension().is_some_and(|ext| ext == "sln"));
+    match (solutions.next(), solutions.next()) {
+        (Some(solution), None) => solution
+            .file_stem()
+            .and_then(|stem| non_empty(&stem.to_string_lossy())),
+        _ => None,
+    }
+}
+
+/// Use the repository name of the `origin` remote of the Git repository at `path`.
+fn git_remote_name(path: &Path) -> Option<String> {
+    read_file(&path.join(".git").join("config"))
+        .and_then(|contents| parse_git_config(&contents))
+        .and_then(|url| repository_name(&url))
+}
+
+/// Parse the URL of the `origin` remote from the `contents` of a Git configuration file.
+fn parse_git_config(contents: &str) -> Option<String> {
+    let mut in_origin = false;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_origin = line == "[remote \"origin\"]";
+        } else if in_origin {
+            if let Some((key, value)) = line.split_once('=') {
+                if key.trim() == "url" {
+                    return non_empty(value);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Get the repository name from a Git remote `url`.
+///
+/// Handle HTTP URLs like `https://github.com/swsnr/mdcat.git` as well as SCP-like URLs like
+/// `git@github.com:swsnr/mdcat.git`.
+fn repository_name(url: &str) -> Option<String> {
+    let url = url.trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    url.rsplit(['/', ':']).next().and_then(non_empty)
+}
+
+/// Use the file name of `path`.
+fn directory_name(path: &Path) -> Option<String> {
+    trace!(
+        "Falling back to file name of {} as project name",
+        path.display()
+    );
+    path.file_name()
+        .and_then(|name| non_empty(&name.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn gradle_settings() {
+        assert_eq!(
+            parse_gradle_settings("include(\"app\")\nrootProject.name = \"mdcat\"\n"),
+            Some("mdcat".to_string())
+        );
+        assert_eq!(
+            parse_gradle_settings("rootProject.name='mdcat'"),
+            Some("mdcat".to_string())
+        );
+        assert_eq!(parse_gradle_settings("include 'app'"), None);
+    }
+
+    #[test]
+    fn maven_pom() {
+        let pom = r#"<?xml version="1.0" encoding="UTF-8"?>
+<project xmlns="http://maven.apache.org/POM/4.0.0">
+  <modelVersion>4.0.0</modelVersion>
+  <parent>
+    <artifactId>parent</artifactId>
+  </parent>
+  <groupId>de.swsnr</groupId>
+  <artifactId>mdcat</artifactId>
+</project>"#;
+        assert_eq!(parse_maven_pom(pom), Some("mdcat".to_string()));
+        assert_eq!(parse_maven_pom("<project></project>"), None);
+    }
+
+    #[test]
+    fn cargo_manifest() {
+        assert_eq!(
+            parse_cargo_manifest("[package]\nname = \"mdcat\"\nversion = \"0.1.0\""),
+            Some("mdcat".to_string())
+        );
+        assert_eq!(
+            parse_cargo_manifest("[workspace]\nmembers = [\"crates/*\"]"),
+            None
+        );
+    }
+
+    #[test]
+    fn package_json() {
+        assert_eq!(
+            parse_package_json(r#"{"name": "mdcat", "version": "1.0.0"}"#),
+            Some("mdcat".to_string())
+        );
+        assert_eq!(parse_package_json(r#"{"private": true}"#), None);
+    }
+
+    #[test]
+    fn git_config() {
+        let config = r#"[core]
+	bare = false
+[remote "upstream"]
+	url = https://github.com/lunaryorn/mdcat.git
+[remote "origin"]
+	url = git@github.com:swsnr/mdcat.git
+	fetch = +refs/heads/*:refs/remotes/origin/*
+"#;
+        assert_eq!(
+            parse_git_config(config),
+            Some("git@github.com:swsnr/mdcat.git".to_string())
+        );
+        assert_eq!(parse_git_config("[core]\n\tbare = false"), None);
+    }
+
+    #[test]
+    fn git_repository_name() {
+        for url in &[
+            "git@github.com:swsnr/mdcat.git",
+            "https://github.com/swsnr/mdcat.git",
+            "https://github.com/swsnr/mdcat/",
+            "ssh://git@example.com:2222/mdcat",
+            "host:mdcat",
+        ] {
+            assert_eq!(repository_name(url), Some("mdcat".to_string()), "{}", url);
+        }
+        assert_eq!(repository_name("git@github.com:"), None);
+    }
+
+    #[test]
+    fn resolve_with_first_matching_resolver() {
+        let resolvers: &[(&str, NameResolver)] = &[
+            ("none", |_| None),
+            ("foo", |_| Some("foo".to_string())),
+            ("bar", |_| Some("bar".to_string())),
+        ];
+        assert_eq!(
+            resolve_project_name(Path::new("/"), resolvers),
+            Some("foo".to_string())
+        );
+        assert_eq!(resolve_project_name(Path::new("/"), &resolvers[..1]), None);
+    }
+
+    #[test]
+    fn solution_names() {
+        let solution = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join("tests")
+            .join("projects")
+            .join("solution")
+            .join("Foo.sln");
+        assert_eq!(
+            solution_idea_root(&solution),
+            Some(solution.with_file_name(".idea").join(".idea.Foo"))
+        );
+        assert_eq!(
+            resolve_solution_name(&solution),
+            Some("Foo Service".to_string())
+        );
+        assert_eq!(
+            resolve_solution_name(Path::new("/does/not/exist/Bar.sln")),
+            Some("Bar".to_string())
+        );
+    }
+
+    #[test]
+    fn resolve_directory_name() {
+        assert_eq!(
+            resolve_project_name(Path::new("/does/not/exist/mdcat"), NAME_RESOLVERS),
+            Some("mdcat".to_string())
+        );
+    }
+}
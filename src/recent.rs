@@ -0,0 +1,666 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Read recent projects from the recent projects files of Jetbrains products.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use log::{trace, warn};
+use xml::common::Position;
+use xml::reader::XmlEvent;
+
+use gnome_search_provider_common::matching::IndexMap;
+
+use crate::macros::PathMacros;
+
+/// A recent project of a Jetbrains product.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentProject {
+    /// The path of the project.
+    pub path: String,
+    /// When the project was last opened, in milliseconds since the epoch.
+    pub open_timestamp: Option<u64>,
+    /// The timestamp of the IDE build which last opened the project, in milliseconds since the epoch.
+    pub build_timestamp: Option<u64>,
+    /// The IDE build which last opened the project, e.g. `IC-211.6693.111`.
+    pub build: Option<String>,
+    /// Whether the project was open when the IDE last saved its recent projects.
+    pub opened: bool,
+    /// The names of all groups this project belongs to.
+    pub groups: Vec<String>,
+}
+
+impl RecentProject {
+    /// When the project was last used, if known.
+    ///
+    /// A project which is still open counts as used right now; otherwise take the
+    /// time the project was last opened.
+    pub fn last_used(&self) -> Option<SystemTime> {
+        if self.opened {
+            Some(SystemTime::now())
+        } else {
+            self.open_timestamp
+                .map(|timestamp| UNIX_EPOCH + Duration::from_millis(timestamp))
+        }
+    }
+}
+
+/// The maximum size of a recent projects file, in bytes.
+const MAX_RECENT_PROJECTS_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The maximum number of entries in a recent projects file.
+///
+/// Both recent projects and the projects of project groups count as entries.
+const MAX_RECENT_PROJECTS_ENTRIES: usize = 10_000;
+
+/// The path of an `entry` of the `additionalInfo` map, relative to the recent projects component.
+const ENTRY_PATH: &[(&str, Option<&str>)] = &[
+    ("option", Some("additionalInfo")),
+    ("map", None),
+    ("entry", None),
+];
+
+/// The path of the metadata of a recent project, relative to its `entry`.
+const META_PATH: &[(&str, Option<&str>)] = &[("value", None), ("RecentProjectMetaInfo", None)];
+
+/// The path of an option in the metadata of a recent project, relative to its `entry`.
+const META_OPTION_PATH: &[(&str, Option<&str>)] = &[
+    ("value", None),
+    ("RecentProjectMetaInfo", None),
+    ("option", None),
+];
+
+/// The path of a project group, relative to the recent projects component.
+const GROUP_PATH: &[(&str, Option<&str>)] = &[
+    ("option", Some("groups")),
+    ("list", None),
+    ("ProjectGroup", None),
+];
+
+/// The path of the name of a project group, relative to the `ProjectGroup`.
+const GROUP_NAME_PATH: &[(&str, Option<&str>)] = &[("option", Some("name"))];
+
+/// The path of a project in a project group, relative to the `ProjectGroup`.
+const GROUP_PROJECT_PATH: &[(&str, Option<&str>)] = &[
+    ("option", Some("projects")),
+    ("list", None),
+    ("option", None),
+];
+
+/// An element which is open while streaming a recent projects file.
+#[derive(Debug)]
+struct OpenElement {
+    /// The local name of the element.
+    name: String,
+    /// The value of the `name` attribute of the element.
+    name_attr: Option<String>,
+}
+
+/// Whether `elements` match `path`, i.e. pairs of element names and optional `name` attributes.
+fn matches_path(elements: &[OpenElement], path: &[(&str, Option<&str>)]) -> bool {
+    elements.len() == path.len()
+        && elements
+            .iter()
+            .zip(path)
+            .all(|(element, (name, name_attr))| {
+                element.name == *name
+                    && name_attr.map_or(true, |attr| element.name_attr.as_deref() == Some(attr))
+            })
+}
+
+/// A recent project while streaming its `entry`.
+#[derive(Debug, Default)]
+struct ProjectEntry {
+    /// The `key` of the entry, i.e. the unexpanded path of the project.
+    key: Option<String>,
+    /// Whether the project was open.
+    opened: bool,
+    /// The options of the metadata of the project.
+    options: HashMap<String, String>,
+}
+
+impl ProjectEntry {
+    /// Get the recent project of this entry.
+    ///
+    /// Return `None` if the entry has no `key`, or if the key contains unknown path macros.
+    fn into_project(self, macros: &PathMacros) -> Option<RecentProject> {
+        let path = match macros.expand(self.key.as_deref()?) {
+            Ok(path) => path,
+            Err(error) => {
+                warn!("Skipping recent project: {:#}", error);
+                return None;
+            }
+        };
+        let timestamp = |name: &str| {
+            self.options
+                .get(name)
+                .and_then(|value| u64::from_str(value).ok())
+        };
+        Some(RecentProject {
+            open_timestamp: timestamp("projectOpenTimestamp"),
+            build_timestamp: timestamp("buildTimestamp"),
+            build: self.options.get("build").cloned(),
+            opened: self.opened,
+            groups: Vec::new(),
+            path,
+        })
+    }
+}
+
+/// A project group while streaming its `ProjectGroup` element.
+#[derive(Debug, Default)]
+struct ProjectGroup {
+    /// The name of the group.
+    name: Option<String>,
+    /// The unexpanded paths of all projects in the group.
+    paths: Vec<String>,
+}
+
+impl ProjectGroup {
+    /// Add all projects of this group to the `groups` of projects.
+    ///
+    /// Skip groups without name, and paths with unknown path macros.
+    fn add_to(self, groups: &mut HashMap<String, Vec<String>>, macros: &PathMacros) {
+        let name = match self.name {
+            Some(name) => name,
+            None => return,
+        };
+        for path in self.paths {
+            match macros.expand(&path) {
+                Ok(path) => groups.entry(path).or_default().push(name.clone()),
+                Err(error) => warn!("Skipping project in group {}: {:#}", name, error),
+            }
+        }
+    }
+}
+
+/// A reader which counts the bytes read from the underlying reader.
+struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.set(self.count.get() + read as u64);
+        Ok(read)
+    }
+}
+
+/// Recent projects read from a recent projects file.
+#[derive(Debug, Default)]
+pub struct RecentProjects {
+    /// All recent projects in the file.
+    pub projects: Vec<RecentProject>,
+    /// Why reading stopped before the end of the XML document, e.g. because the file is
+    /// truncated after an IDE crashed while writing it.
+    ///
+    /// `projects` then contains all projects read before the error.
+    pub error: Option<anyhow::Error>,
+}
+
+/// Read all recent projects from the given `reader`, and expand path `macros` in project paths.
+///
+/// Stream the XML document, and stop at malformed XML or after more than
+/// [`MAX_RECENT_PROJECTS_ENTRIES`] entries, but keep all projects read so far.
+fn read_recent_jetbrains_projects<R: Read>(
+    reader: R,
+    macros: &PathMacros,
+) -> Result<RecentProjects> {
+    let offset = Rc::new(Cell::new(0));
+    let mut events = xml::EventReader::new(CountingReader {
+        inner: reader,
+        count: offset.clone(),
+    });
+    let mut elements: Vec<OpenElement> = Vec::new();
+    // The depth of the recent projects component, while inside the component
+    let mut component: Option<usize> = None;
+    let mut seen_component = false;
+    let mut entries = 0;
+    let mut entry: Option<ProjectEntry> = None;
+    let mut group: Option<ProjectGroup> = None;
+    let mut projects = Vec::new();
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+
+    let error = loop {
+        let event = match events.next() {
+            Ok(event) => event,
+            Err(error) => {
+                let position = error.position();
+                break Some(anyhow!(
+                    "Malformed XML at byte {} (line {}, column {}): {}",
+                    offset.get(),
+                    position.row + 1,
+                    position.column + 1,
+                    error.msg()
+                ));
+            }
+        };
+        match event {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let attr = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|attr| attr.name.local_name == key)
+                        .map(|attr| attr.value.clone())
+                };
+                elements.push(OpenElement {
+                    name: name.local_name,
+                    name_attr: attr("name"),
+                });
+                let depth = match component {
+                    Some(depth) => depth,
+                    None => {
+                        let is_component = elements.len() == 2
+                            && elements[1].name == "component"
+                            && matches!(
+                                elements[1].name_attr.as_deref(),
+                                Some("RecentProjectsManager") | Some("RiderRecentProjectsManager")
+                            );
+                        if is_component && !seen_component {
+                            component = Some(elements.len());
+                            seen_component = true;
+                        }
+                        continue;
+                    }
+                };
+                let path = &elements[depth..];
+                if matches_path(path, ENTRY_PATH) {
+                    entries += 1;
+                    entry = Some(ProjectEntry {
+                        key: attr("key"),
+                        ..ProjectEntry::default()
+                    });
+                } else if matches_path(path, GROUP_PATH) {
+                    group = Some(ProjectGroup::default());
+                } else if let Some(entry) = entry.as_mut() {
+                    let meta = &path[ENTRY_PATH.len()..];
+                    if matches_path(meta, META_PATH) {
+                        entry.opened = attr("opened").as_deref() == Some("true");
+                    } else if matches_path(meta, META_OPTION_PATH) {
+                        if let (Some(name), Some(value)) = (attr("name"), attr("value")) {
+                            entry.options.entry(name).or_insert(value);
+                        }
+                    }
+                } else if let Some(group) = group.as_mut() {
+                    let member = &path[GROUP_PATH.len()..];
+                    if matches_path(member, GROUP_NAME_PATH) {
+                        if let Some(value) = attr("value") {
+                            group.name.get_or_insert(value);
+                        }
+                    } else if matches_path(member, GROUP_PROJECT_PATH) {
+                        entries += 1;
+                        group.paths.extend(attr("value"));
+                    }
+                }
+                if MAX_RECENT_PROJECTS_ENTRIES < entries {
+                    break Some(anyhow!(
+                        "More than {} entries at byte {}",
+                        MAX_RECENT_PROJECTS_ENTRIES,
+                        offset.get()
+                    ));
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                if let Some(depth) = component {
+                    let path = &elements[depth..];
+                    if matches_path(path, ENTRY_PATH) {
+                        projects.extend(entry.take().and_then(|e| e.into_project(macros)));
+                    } else if matches_path(path, GROUP_PATH) {
+                        if let Some(group) = group.take() {
+                            group.add_to(&mut groups, macros);
+                        }
+                    } else if elements.len() == depth {
+                        component = None;
+                    }
+                }
+                elements.pop();
+            }
+            XmlEvent::EndDocument => break None,
+            _ => {}
+        }
+    };
+
+    let projects = projects
+        .into_iter()
+        .map(|project: RecentProject| RecentProject {
+            groups: groups.remove(&project.path).unwrap_or_default(),
+            ..project
+        })
+        .collect();
+    let projects = RecentProjects { projects, error };
+    trace!("Parsed projects {:?}", projects);
+    Ok(projects)
+}
+
+/// Read all recent projects from the recent projects `file` of a product.
+///
+/// Expand path macros of the configuration directory which contains `file`.  Read no projects
+/// from `file` if it's larger than [`MAX_RECENT_PROJECTS_FILE_SIZE`], and return an error for
+/// `file` instead.
+pub fn read_recent_projects_file(file: &Path) -> Result<RecentProjects> {
+    let config_dir = file
+        .parent()
+        .and_then(Path::parent)
+        .with_context(|| format!("No configuration directory for {}", file.display()))?;
+    let macros = PathMacros::load(config_dir)?;
+    let source = File::open(file).with_context(|| format!("Failed to open {}", file.display()))?;
+    let size = source
+        .metadata()
+        .with_context(|| format!("Failed to open {}", file.display()))?
+        .len();
+    if MAX_RECENT_PROJECTS_FILE_SIZE < size {
+        return Ok(RecentProjects {
+            projects: Vec::new(),
+            error: Some(anyhow!(
+                "{} has {} bytes, more than the maximum of {} bytes",
+                file.display(),
+                size,
+                MAX_RECENT_PROJECTS_FILE_SIZE
+            )),
+        });
+    }
+    // Limit reading in case the file grows while we read it
+    let reader = BufReader::new(source).take(MAX_RECENT_PROJECTS_FILE_SIZE);
+    let projects = read_recent_jetbrains_projects(reader, &macros)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    Ok(RecentProjects {
+        error: projects
+            .error
+            .map(|error| error.context(format!("Failed to read {}", file.display()))),
+        ..projects
+    })
+}
+
+/// Merge recent projects from different product versions.
+///
+/// Deduplicate projects by path and keep the newest metadata for every project, that is,
+/// the metadata with the latest open timestamp, or, if both are equal, the latest build timestamp.
+/// Keep the order of first occurrence.
+pub fn merge_recent_projects<I: IntoIterator<Item = RecentProject>>(
+    projects: I,
+) -> Vec<RecentProject> {
+    let mut merged: IndexMap<String, RecentProject> = IndexMap::new();
+    for project in projects {
+        match merged.get_mut(&project.path) {
+            Some(existing) => {
+                if (existing.open_timestamp, existing.build_timestamp)
+                    < (project.open_timestamp, project.build_timestamp)
+                {
+                    *existing = project;
+                }
+            }
+            None => {
+                merged.insert(project.path.clone(), project);
+            }
+        }
+    }
+    merged.into_iter().map(|(_, project)| project).collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// A recent project at `path` which was never opened or built, and has no groups.
+    pub fn recent_project(path: &str) -> RecentProject {
+        RecentProject {
+            path: path.to_string(),
+            open_timestamp: None,
+            build_timestamp: None,
+            build: None,
+            opened: false,
+            groups: vec![],
+        }
+    }
+
+    #[test]
+    fn read_recent_projects() {
+        let data: &[u8] = include_bytes!("tests/recentProjects.xml");
+        let home = dirs::home_dir().unwrap();
+        let macros = PathMacros::builtin(&home.to_string_lossy(), "/config");
+        let items = read_recent_jetbrains_projects(data, &macros).unwrap();
+
+        assert!(items.error.is_none());
+        assert_eq!(
+            items.projects,
+            vec![
+                RecentProject {
+                    path: home
+                        .join("Code")
+                        .join("gh")
+                        .join("mdcat")
+                        .to_string_lossy()
+                        .to_string(),
+                    open_timestamp: Some(1618242624090),
+                    build_timestamp: Some(1611627898456),
+                    build: Some("IC-203.7148.57".to_string()),
+                    opened: false,
+                    groups: vec!["work".to_string(), "rust".to_string()],
+                },
+                RecentProject {
+                    path: home
+                        .join("Code")
+                        .join("gh")
+                        .join("gnome-search-providers-jetbrains")
+                        .to_string_lossy()
+                        .to_string(),
+                    open_timestamp: Some(1618243465479),
+                    build_timestamp: Some(1617703863501),
+                    build: Some("IC-211.6693.111".to_string()),
+                    opened: true,
+                    groups: vec![],
+                }
+            ]
+        )
+    }
+
+    #[test]
+    fn read_recent_solutions() {
+        let data: &[u8] = include_bytes!("tests/recentSolutions.xml");
+        let home = dirs::home_dir().unwrap();
+        let macros = PathMacros::builtin(&home.to_string_lossy(), "/config");
+        let items = read_recent_jetbrains_projects(data, &macros).unwrap();
+
+        assert_eq!(
+            items
+                .projects
+                .into_iter()
+                .map(|p| p.path)
+                .collect::<Vec<_>>(),
+            vec![
+                home.join("Code")
+                    .join("gh")
+                    .join("mdcat")
+                    .to_string_lossy()
+                    .to_string(),
+                home.join("Code")
+                    .join("gh")
+                    .join("gnome-search-providers-jetbrains")
+                    .to_string_lossy()
+                    .to_string(),
+                home.join("Code")
+                    .join("gh")
+                    .join("Foo")
+                    .join("Foo.sln")
+                    .to_string_lossy()
+                    .to_string()
+            ]
+        )
+    }
+
+    #[test]
+    fn skip_recent_projects_with_unknown_macros() {
+        let data = r#"<application>
+  <component name="RecentProjectsManager">
+    <option name="additionalInfo">
+      <map>
+        <entry key="$PROJECT_DIR$/mdcat" />
+        <entry key="$CODE$/mdcat" />
+      </map>
+    </option>
+  </component>
+</application>"#;
+        let mut macros = PathMacros::builtin("/home/foo", "/config");
+        macros
+            .add_custom(
+                r#"<application><component name="PathMacrosImpl"><macro name="CODE" value="/srv/code" /></component></application>"#
+                    .as_bytes(),
+            )
+            .unwrap();
+        let projects = read_recent_jetbrains_projects(data.as_bytes(), &macros).unwrap();
+        assert_eq!(
+            projects
+                .projects
+                .into_iter()
+                .map(|p| p.path)
+                .collect::<Vec<_>>(),
+            vec!["/srv/code/mdcat".to_string()]
+        );
+    }
+
+    #[test]
+    fn read_truncated_recent_projects() {
+        let data = include_str!("tests/recentProjects.xml");
+        let end = data.find("</entry>").unwrap() + "</entry>".len() + 30;
+        let macros = PathMacros::builtin("/home/foo", "/config");
+        let projects = read_recent_jetbrains_projects(&data.as_bytes()[..end], &macros).unwrap();
+        assert_eq!(
+            projects
+                .projects
+                .iter()
+                .map(|p| (p.path.as_str(), p.groups.len()))
+                .collect::<Vec<_>>(),
+            vec![("/home/foo/Code/gh/mdcat", 2)]
+        );
+        let error = format!("{:#}", projects.error.unwrap());
+        assert!(
+            error.starts_with(&format!("Malformed XML at byte {} ", end)),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn stop_at_too_many_recent_projects() {
+        let entries = (0..=MAX_RECENT_PROJECTS_ENTRIES)
+            .map(|i| format!("<entry key=\"/home/foo/{}\" />", i))
+            .collect::<String>();
+        let data = format!(
+            r#"<application><component name="RecentProjectsManager"><option name="additionalInfo"><map>{}</map></option></component></application>"#,
+            entries
+        );
+        let macros = PathMacros::builtin("/home/foo", "/config");
+        let projects = read_recent_jetbrains_projects(data.as_bytes(), &macros).unwrap();
+        assert_eq!(projects.projects.len(), MAX_RECENT_PROJECTS_ENTRIES);
+        let error = projects.error.unwrap();
+        assert!(
+            error.to_string().starts_with("More than 10000 entries"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn skip_too_large_recent_projects_file() {
+        let config_dir = std::env::temp_dir().join(format!(
+            "gnome-search-providers-jetbrains-large-{}",
+            std::process::id()
+        ));
+        let file = config_dir.join("options").join("recentProjects.xml");
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        File::create(&file)
+            .unwrap()
+            .set_len(MAX_RECENT_PROJECTS_FILE_SIZE + 1)
+            .unwrap();
+        let projects = read_recent_projects_file(&file);
+        std::fs::remove_dir_all(&config_dir).unwrap();
+        let projects = projects.unwrap();
+        assert!(projects.projects.is_empty());
+        let error = projects.error.unwrap().to_string();
+        assert!(error.contains("more than the maximum"), "{}", error);
+    }
+
+    #[test]
+    fn recent_project_last_used() {
+        let project = RecentProject {
+            open_timestamp: Some(1618242624090),
+            ..recent_project("/home/foo/dev/mdcat")
+        };
+        assert_eq!(
+            project.last_used(),
+            Some(UNIX_EPOCH + Duration::from_millis(1618242624090))
+        );
+
+        let open_project = RecentProject {
+            opened: true,
+            ..project
+        };
+        assert!(open_project.last_used().unwrap() > UNIX_EPOCH + Duration::from_secs(1618243465));
+    }
+
+    #[test]
+    fn merge_recent_projects_keeps_newest_metadata() {
+        let project = |path: &str, open_timestamp, build_timestamp| RecentProject {
+            open_timestamp,
+            build_timestamp,
+            ..recent_project(path)
+        };
+        let merged = merge_recent_projects(vec![
+            project(
+                "/home/foo/dev/mdcat",
+                Some(1618242624090),
+                Some(1617703863501),
+            ),
+            project("/home/foo/dev/bar", Some(1618242624090), None),
+            project(
+                "/home/foo/dev/mdcat",
+                Some(1618243465479),
+                Some(1611627898456),
+            ),
+            project(
+                "/home/foo/dev/bar",
+                Some(1618242624090),
+                Some(1611627898456),
+            ),
+            project("/home/foo/dev/foo", None, None),
+            project(
+                "/home/foo/dev/mdcat",
+                Some(1611627898456),
+                Some(1617703863501),
+            ),
+        ]);
+        assert_eq!(
+            merged,
+            vec![
+                project(
+                    "/home/foo/dev/mdcat",
+                    Some(1618243465479),
+                    Some(1611627898456)
+                ),
+                project(
+                    "/home/foo/dev/bar",
+                    Some(1618242624090),
+                    Some(1611627898456)
+                ),
+                project("/home/foo/dev/foo", None, None),
+            ]
+        );
+    }
+}
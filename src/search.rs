@@ -0,0 +1,339 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Search recent projects from the command line, without DBus.
+
+use std::cmp::Ordering;
+use std::time::SystemTime;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use gnome_search_provider_common::app::{app_match_text, AppLaunchItem};
+use gnome_search_provider_common::export::gio;
+use gnome_search_provider_common::history::History;
+use gnome_search_provider_common::matching::*;
+
+use crate::config::Config;
+use crate::source::JetbrainsProjectsSource;
+use crate::{ProviderDefinition, VersionSelection};
+
+/// A scored search result.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SearchResult {
+    /// The ID of the result, as the search provider returns it.
+    pub id: String,
+    /// The name of the project.
+    pub name: String,
+    /// The path of the project, or the URI for remote projects.
+    pub path: String,
+    /// The description of the project.
+    pub description: Option<String>,
+    /// The label of the provider which found the project.
+    pub provider: String,
+    /// The score of the project for the search terms, including the boost from the history if
+    /// results are ranked.
+    pub score: f64,
+}
+
+impl SearchResult {
+    /// Format this result as a line of tab-separated fields.
+    ///
+    /// Fields are score, name, path, provider and ID, in this order.
+    pub fn to_tsv(&self) -> String {
+        format!(
+            "{:.3}\t{}\t{}\t{}\t{}",
+            self.score, self.name, self.path, self.provider, self.id
+        )
+    }
+}
+
+/// Score `items` of `provider` against `terms` with `mode`.
+///
+/// Match `ide:` terms against the app of every item like the search provider does, i.e.
+/// against `app` for items without an app of their own.
+///
+/// Return results with non-zero score only, ordered by score in descending order.
+fn score_items<T: AsRef<str>>(
+    provider: &str,
+    app: &str,
+    items: &IdMap<AppLaunchItem>,
+    mode: MatchMode,
+    terms: &[T],
+) -> Vec<SearchResult> {
+    let items_in_app = items.iter().map(|(id, item)| {
+        (
+            id,
+            InApp {
+                item,
+                app: item
+                    .app
+                    .as_ref()
+                    .map_or_else(|| app.to_string(), app_match_text),
+            },
+        )
+    });
+    find_scored_items(items_in_app, mode, &Query::parse(terms))
+        .into_iter()
+        .map(|(score, id)| {
+            let item = &items[id];
+            SearchResult {
+                id: id.clone(),
+                name: item.name.clone(),
+                path: item.target.description().to_string(),
+                description: item.description.clone(),
+                provider: provider.to_string(),
+                score,
+            }
+        })
+        .collect()
+}
+
+/// Rank `results` of a provider for `terms` at `now` like the search provider does.
+///
+/// Discard results below the minimum score of `limits`, boost results with `history`, and
+/// cut results to the maximum number of results of `limits`.
+///
+/// Return results ordered by boosted score in descending order.
+fn rank_results<T: AsRef<str>>(
+    results: Vec<SearchResult>,
+    terms: &[T],
+    limits: ResultLimits,
+    history: &History,
+    now: SystemTime,
+) -> Vec<SearchResult> {
+    let mut results: IdMap<SearchResult> = results
+        .into_iter()
+        .map(|result| (result.id.clone(), result))
+        .collect();
+    let mut scored: Vec<(f64, String)> = results
+        .values()
+        .map(|result| (result.score, result.id.clone()))
+        .collect();
+    limits.retain_min_score(&mut scored);
+    let mut ranked: Vec<SearchResult> = history
+        .boost_all(terms, scored, now)
+        .into_iter()
+        .filter_map(|(score, id)| {
+            results
+                .swap_remove(&id)
+                .map(|result| SearchResult { score, ..result })
+        })
+        .collect();
+    limits.truncate(&mut ranked);
+    ranked
+}
+
+/// Whether `provider` matches the `filter` given on the command line.
+///
+/// The filter matches the label or the relative object path of the provider, ignoring case.
+fn provider_matches(provider: &ProviderDefinition, filter: &str) -> bool {
+    provider.label.eq_ignore_ascii_case(filter)
+        || provider.relative_obj_path.eq_ignore_ascii_case(filter)
+}
+
+/// Search recent projects of all providers in `config` for `terms`.
+///
+/// If `filter` is given only search providers whose label or relative object path matches
+/// `filter`.  Read recent projects from the given `versions` of every product, and score
+/// projects with the match mode of each provider.
+///
+/// If `history` is given, rank the results of every provider like the search provider does,
+/// see [`rank_results`]; otherwise return all matching projects with their plain score.
+///
+/// Return results ordered by score in descending order; skip providers whose recent projects
+/// fail to load, and log the error.
+pub fn search<T: AsRef<str>>(
+    config: &Config,
+    versions: VersionSelection,
+    filter: Option<&str>,
+    terms: &[T],
+    history: Option<&History>,
+) -> Result<Vec<SearchResult>> {
+    let now = SystemTime::now();
+    let mut results = Vec::new();
+    let providers = config
+        .providers()
+        .filter(|provider| filter.map_or(true, |filter| provider_matches(provider, filter)))
+        .collect::<Vec<_>>();
+    if let (Some(filter), true) = (filter, providers.is_empty()) {
+        anyhow::bail!("No provider matches {}", filter);
+    }
+    for provider in providers {
+        let source = JetbrainsProjectsSource::new(
+            provider.desktop_id.to_string(),
+            provider.config,
+            provider.config.config_home.path(),
+            versions,
+            config.missing_projects(&provider),
+            config.description(&provider),
+            None,
+        );
+        // Fall back to the label if the app isn't installed, which the search provider
+        // wouldn't serve at all.
+        let app = gio::DesktopAppInfo::new(provider.desktop_id).map_or_else(
+            || format!("{} {}", provider.label, provider.desktop_id),
+            |app| app_match_text(&app),
+        );
+        match source.find_recent_items() {
+            Ok(items) => {
+                let scored = score_items(
+                    provider.label,
+                    &app,
+                    &items,
+                    config.match_mode(&provider),
+                    terms,
+                );
+                let ranked = match history {
+                    Some(history) => {
+                        rank_results(scored, terms, config.result_limits(&provider), history, now)
+                    }
+                    None => scored,
+                };
+                results.extend(ranked.into_iter().map(|result| SearchResult {
+                    description: source.describe_item(&result.id).or(result.description),
+                    ..result
+                }));
+            }
+            Err(error) => log::warn!(
+                "Skipping recent projects of {}: {:#}",
+                provider.label,
+                error
+            ),
+        }
+    }
+    results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use gnome_search_provider_common::app::AppLaunchTarget;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn item(name: &str, path: &str) -> AppLaunchItem {
+        AppLaunchItem {
+            name: name.to_string(),
+            target: AppLaunchTarget::File(path.to_string()),
+            last_used: None,
+            keywords: Vec::new(),
+            description: Some(format!("at {}", path)),
+            icon: None,
+            app: None,
+        }
+    }
+
+    #[test]
+    fn score_items_orders_by_score() {
+        let mut items = IndexMap::new();
+        items.insert("bar".to_string(), item("bar", "/home/foo/dev/bar"));
+        items.insert("mdcat-foo".to_string(), item("foo", "/home/foo/dev/mdcat"));
+        items.insert("mdcat".to_string(), item("mdcat", "/home/foo/dev/mdcat"));
+        let results = score_items(
+            "IDEA",
+            "IntelliJ IDEA jetbrains-idea.desktop",
+            &items,
+            MatchMode::Fuzzy,
+            &["mdcat"],
+        );
+        assert_eq!(
+            results.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(),
+            vec!["mdcat", "mdcat-foo"]
+        );
+        assert_eq!(results[0].name, "mdcat");
+        assert_eq!(results[0].path, "/home/foo/dev/mdcat");
+        assert_eq!(
+            results[0].description.as_deref(),
+            Some("at /home/foo/dev/mdcat")
+        );
+        assert_eq!(results[0].provider, "IDEA");
+        assert!(results[1].score < results[0].score);
+    }
+
+    #[test]
+    fn score_items_matches_ide_like_the_search_provider() {
+        let mut items = IndexMap::new();
+        items.insert("mdcat".to_string(), item("mdcat", "/home/foo/dev/mdcat"));
+        let app = "IntelliJ IDEA jetbrains-idea.desktop";
+        for (terms, matches) in [
+            (["ide:intellij"], true),
+            (["ide:jetbrains-idea"], true),
+            (["ide:rider"], false),
+        ] {
+            let results = score_items("IDEA", app, &items, MatchMode::Substring, &terms);
+            assert_eq!(results.len(), usize::from(matches), "{:?}", terms);
+        }
+    }
+
+    #[test]
+    fn rank_results_like_the_search_provider() {
+        let result = |id: &str, score: f64| SearchResult {
+            id: id.to_string(),
+            name: id.to_string(),
+            path: format!("/home/foo/dev/{}", id),
+            description: None,
+            provider: "IDEA".to_string(),
+            score,
+        };
+        let results = vec![
+            result("mdcat", 12.0),
+            result("mdless", 10.5),
+            result("md", 8.0),
+            result("rapid", 1.0),
+        ];
+        let now = SystemTime::now();
+        let mut history = History::default();
+        history.record(&["md"], "mdless", now);
+        let limits = ResultLimits {
+            max_results: Some(3),
+            min_score: 2.0,
+        };
+
+        let ranked = rank_results(results, &["md"], limits, &history, now);
+        assert_eq!(
+            ranked.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(),
+            vec!["mdless", "mdcat", "md"]
+        );
+        assert!(ranked[0].score > 12.0);
+
+        let limits = ResultLimits {
+            max_results: Some(1),
+            min_score: 0.0,
+        };
+        let ranked = rank_results(ranked, &["md"], limits, &History::default(), now);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].id, "mdless");
+    }
+
+    #[test]
+    fn result_as_tsv() {
+        let result = SearchResult {
+            id: "jetbrains-recent-project-foo".to_string(),
+            name: "mdcat".to_string(),
+            path: "/home/foo/dev/mdcat".to_string(),
+            description: None,
+            provider: "IDEA".to_string(),
+            score: 0.5,
+        };
+        assert_eq!(
+            result.to_tsv(),
+            "0.500\tmdcat\t/home/foo/dev/mdcat\tIDEA\tjetbrains-recent-project-foo"
+        );
+    }
+
+    #[test]
+    fn provider_filter() {
+        let provider = crate::PROVIDERS
+            .iter()
+            .find(|p| p.relative_obj_path == "toolbox/clion")
+            .unwrap();
+        assert!(provider_matches(provider, "toolbox/clion"));
+        assert!(provider_matches(provider, "clion (Toolbox)"));
+        assert!(!provider_matches(provider, "clion"));
+    }
+}
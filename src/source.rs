@@ -0,0 +1,1150 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Load, cache and describe recent projects for search providers.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, trace, warn};
+
+use gnome_search_provider_common::app::*;
+use gnome_search_provider_common::export::gio;
+use gnome_search_provider_common::export::gio::prelude::*;
+use gnome_search_provider_common::export::glib;
+use gnome_search_provider_common::matching::*;
+
+use crate::description::*;
+use crate::missing::{is_on_slow_mount, MissingProjects, MountPoint};
+use crate::names::{
+    resolve_project_name, resolve_solution_name, solution_idea_root, NAME_RESOLVERS,
+};
+use crate::recent::{merge_recent_projects, read_recent_projects_file, RecentProject};
+use crate::{
+    find_project_icon, is_solution, parse_project_location, project_directory, ConfigLocation,
+    ProjectLocation, VersionSelection,
+};
+
+/// A recent project along with the search result for it.
+#[derive(Debug, Clone, PartialEq)]
+struct ProjectItem {
+    project: RecentProject,
+    /// The name of the project.
+    name: String,
+    /// What to launch for the project.
+    target: AppLaunchTarget,
+    /// The icon of the project, if any.
+    icon: Option<PathBuf>,
+}
+
+impl ProjectItem {
+    /// Get the search result for this project.
+    ///
+    /// The search result has no description; describe the project when it's shown.
+    fn launch_item(&self) -> AppLaunchItem {
+        AppLaunchItem {
+            name: self.name.clone(),
+            target: self.target.clone(),
+            last_used: self.project.last_used(),
+            keywords: self.project.groups.clone(),
+            description: None,
+            icon: self.icon.clone(),
+            app: None,
+        }
+    }
+
+    /// Whether the directory of this project no longer exists.
+    ///
+    /// Assume that projects on slow `mounts` exist, to not block on unreachable file systems.
+    fn is_missing(&self, mounts: &[MountPoint]) -> bool {
+        match &self.target {
+            AppLaunchTarget::File(path) => {
+                let path = Path::new(path);
+                if is_on_slow_mount(mounts, path) {
+                    trace!("Not checking {} on slow mount", path.display());
+                    false
+                } else {
+                    !path.exists()
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A file a worker read to load recent projects.
+#[derive(Debug, PartialEq)]
+struct ReadFile {
+    path: PathBuf,
+    /// Whether the file is a directory.
+    directory: bool,
+    /// When the file was modified last before the worker read it, or `None` if it didn't exist.
+    modified: Option<SystemTime>,
+}
+
+impl ReadFile {
+    /// Get the modification time of the file at `path`, if any.
+    fn modified(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Remember the current modification time of the file at `path`, before reading it.
+    fn before_reading(path: PathBuf, directory: bool) -> Self {
+        Self {
+            modified: Self::modified(&path),
+            path,
+            directory,
+        }
+    }
+
+    /// Whether the file changed since the worker read it.
+    fn changed(&self) -> bool {
+        Self::modified(&self.path) != self.modified
+    }
+}
+
+/// Recent projects along with the projects to hide.
+#[derive(Debug, PartialEq)]
+struct LoadedItems {
+    /// All recent projects.
+    items: Arc<IdMap<ProjectItem>>,
+    /// The IDs of recent projects to hide.
+    hidden: HashSet<String>,
+    /// The files recent projects were read from, or `None` for cached projects.
+    read: Option<Vec<ReadFile>>,
+}
+
+impl LoadedItems {
+    /// Get search results for all recent projects to show.
+    fn launch_items(&self) -> IdMap<AppLaunchItem> {
+        self.items
+            .iter()
+            .filter(|(id, _)| !self.hidden.contains(*id))
+            .map(|(id, item)| (id.clone(), item.launch_item()))
+            .collect()
+    }
+}
+
+/// Loads and describes recent projects of an app.
+///
+/// Owns everything it needs, so that it can load recent projects on a worker thread.
+#[derive(Debug, Clone)]
+struct ProjectsLoader {
+    app_id: String,
+    /// How to handle projects whose directory no longer exists.
+    missing_projects: MissingProjects,
+    /// The template for descriptions of projects.
+    description: DescriptionTemplate,
+}
+
+impl ProjectsLoader {
+    /// Read recent projects from all existing `files`.
+    ///
+    /// Skip files which fail to read, and merge projects from all other files.
+    fn read_recent_projects(&self, files: &[PathBuf]) -> Vec<RecentProject> {
+        let mut projects = Vec::new();
+        for file in files.iter().filter(|file| file.is_file()) {
+            match read_recent_projects_file(file) {
+                Ok(recent) => {
+                    if let Some(error) = recent.error {
+                        warn!("Skipping rest of recent projects: {:#}", error);
+                    }
+                    projects.extend(recent.projects);
+                }
+                Err(error) => warn!("Skipping recent projects: {:#}", error),
+            }
+        }
+        merge_recent_projects(projects)
+    }
+
+    /// Get the item for a recent `project`.
+    ///
+    /// For projects on slow `mounts` take the name from the path, and do not look for an icon,
+    /// to not block on unreachable file systems.
+    ///
+    /// Return `None` if the project cannot be opened.
+    fn project_item(&self, project: RecentProject, mounts: &[MountPoint]) -> Option<ProjectItem> {
+        let path = project.path.clone();
+        let (name, target, icon) = match parse_project_location(&path) {
+            ProjectLocation::Local(path) => {
+                let (name, icon) = if is_on_slow_mount(mounts, Path::new(path)) {
+                    trace!("Not resolving name and icon of {} on slow mount", path);
+                    let name = if is_solution(Path::new(path)) {
+                        Path::new(path).file_stem()
+                    } else {
+                        Path::new(path).file_name()
+                    };
+                    (name.map(|name| name.to_string_lossy().to_string()), None)
+                } else if is_solution(Path::new(path)) {
+                    // Rider keeps name and icon of a solution in a separate .idea directory
+                    (
+                        resolve_solution_name(Path::new(path)),
+                        solution_idea_root(Path::new(path)).and_then(find_project_icon),
+                    )
+                } else {
+                    (
+                        resolve_project_name(Path::new(path), NAME_RESOLVERS),
+                        find_project_icon(path),
+                    )
+                };
+                (name, AppLaunchTarget::File(path.to_string()), icon)
+            }
+            ProjectLocation::Ssh(ssh) => {
+                let name = Path::new(ssh.path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string());
+                (name, AppLaunchTarget::Uri(ssh.gateway_uri()), None)
+            }
+            ProjectLocation::Unsupported => {
+                trace!("Skipping {}, cannot open remote project", path);
+                return None;
+            }
+        };
+        if name.is_none() {
+            trace!("Skipping {}, failed to determine project name", path);
+        }
+        name.map(|name| ProjectItem {
+            project,
+            name,
+            target,
+            icon,
+        })
+    }
+
+    /// Load recent items from the recent projects `files`, given the current `mounts`.
+    fn load_recent_items(&self, files: &[PathBuf], mounts: &[MountPoint]) -> IdMap<ProjectItem> {
+        info!("Searching recent projects for {}", self.app_id);
+        let mut items = IndexMap::new();
+        for project in self.read_recent_projects(files) {
+            let id = format!("jetbrains-recent-project-{}-{}", self.app_id, project.path);
+            if let Some(item) = self.project_item(project, mounts) {
+                trace!(
+                    "Found project {} at {} for {} (opened at {:?}, open: {}, build from {:?})",
+                    item.name,
+                    item.project.path,
+                    self.app_id,
+                    item.project.open_timestamp,
+                    item.project.opened,
+                    item.project.build_timestamp
+                );
+                items.insert(id, item);
+            }
+        }
+        info!("Found {} project(s) for {}", items.len(), self.app_id,);
+        items
+    }
+
+    /// Describe the given `project` with the description template.
+    ///
+    /// Only read the Git branch of projects on the local file system, and not on slow `mounts`.
+    fn describe(&self, project: &RecentProject, mounts: &[MountPoint]) -> String {
+        let home = dirs::home_dir();
+        self.description.render(|field| match field {
+            Field::Path => Some(project.path.clone()),
+            Field::ShortPath => Some(match &home {
+                Some(home) => home_relative_path(&project.path, home),
+                None => project.path.clone(),
+            }),
+            Field::Directory => Some(match parse_project_location(&project.path) {
+                ProjectLocation::Local(path) => {
+                    project_directory(Path::new(path)).display().to_string()
+                }
+                _ => project.path.clone(),
+            }),
+            Field::Groups => Some(project.groups.join(", ")).filter(|g| !g.is_empty()),
+            Field::Branch => match parse_project_location(&project.path) {
+                ProjectLocation::Local(path) if !is_on_slow_mount(mounts, Path::new(path)) => {
+                    git_branch(project_directory(Path::new(path)))
+                }
+                _ => None,
+            },
+            Field::Opened => project
+                .last_used()
+                .map(|time| humanize_age(time.elapsed().unwrap_or_default())),
+            Field::Build => project.build.clone(),
+        })
+    }
+
+    /// Describe the given project `item`, and mark it if its directory no longer exists.
+    fn describe_item(&self, item: &ProjectItem, mounts: &[MountPoint]) -> String {
+        let description = self.describe(&item.project, mounts);
+        if self.missing_projects == MissingProjects::Show && item.is_missing(mounts) {
+            format!("missing: {}", description)
+        } else {
+            description
+        }
+    }
+
+    /// Get the IDs of all recent project `items` to hide.
+    ///
+    /// Hide projects whose directory no longer exists, unless missing projects should be shown.
+    fn hidden_items(&self, items: &IdMap<ProjectItem>, mounts: &[MountPoint]) -> HashSet<String> {
+        match self.missing_projects {
+            MissingProjects::Show => HashSet::new(),
+            MissingProjects::Hide => items
+                .iter()
+                .filter(|(_, item)| item.is_missing(mounts))
+                .map(|(id, item)| {
+                    debug!("Hiding missing project {}", item.project.path);
+                    id.clone()
+                })
+                .collect(),
+        }
+    }
+}
+
+/// How long to wait for the first load of recent projects before answering a search without
+/// results.
+pub const LOAD_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Recent projects being loaded on a worker thread.
+struct PendingLoad {
+    /// The generation of the cache when loading started.
+    generation: u64,
+    /// Receives the result from the worker.
+    receiver: mpsc::Receiver<Result<LoadedItems>>,
+}
+
+pub struct JetbrainsProjectsSource<'a> {
+    pub app_id: String,
+    /// Where to look for the configuration and the list of recent projects.
+    pub config: ConfigLocation<'a>,
+    /// The directory which contains the vendor configuration directory, if any.
+    config_home: Option<PathBuf>,
+    /// Which product versions to read recent projects from.
+    pub versions: VersionSelection,
+    /// Loads and describes recent projects.
+    loader: ProjectsLoader,
+    /// How long to wait for recent projects, or `None` to wait until they're loaded.
+    load_timeout: Option<Duration>,
+    /// Recent projects from the last search, until a change to the configuration invalidates them.
+    cache: Rc<RefCell<Option<Arc<IdMap<ProjectItem>>>>>,
+    /// Counts invalidations of the cache, to not cache results of outdated loads.
+    generation: Rc<Cell<u64>>,
+    /// Monitors which invalidate the cache when the configuration changes.
+    monitors: RefCell<Vec<gio::FileMonitor>>,
+    /// Recent projects currently being loaded, if any.
+    pending: RefCell<Option<PendingLoad>>,
+    /// The recent projects of the last successful load.
+    snapshot: RefCell<Option<LoadedItems>>,
+    /// The error of the last attempt to load recent projects, if it failed.
+    pub last_error: RefCell<Option<String>>,
+    /// How often this source started loading recent projects.
+    loads: Cell<u64>,
+}
+
+impl<'a> JetbrainsProjectsSource<'a> {
+    /// Create a new source for recent projects of the app with the given `app_id`.
+    ///
+    /// Look for the vendor configuration directory in `config_home`, usually the path of
+    /// `config.config_home`.  Wait at most `load_timeout` for recent projects, or until they're
+    /// loaded if `None`.
+    pub fn new(
+        app_id: String,
+        config: ConfigLocation<'a>,
+        config_home: Option<PathBuf>,
+        versions: VersionSelection,
+        missing_projects: MissingProjects,
+        description: DescriptionTemplate,
+        load_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            loader: ProjectsLoader {
+                app_id: app_id.clone(),
+                missing_projects,
+                description,
+            },
+            app_id,
+            config,
+            config_home,
+            versions,
+            load_timeout,
+            cache: Rc::new(RefCell::new(None)),
+            generation: Rc::new(Cell::new(0)),
+            monitors: RefCell::new(Vec::new()),
+            pending: RefCell::new(None),
+            snapshot: RefCell::new(None),
+            last_error: RefCell::new(None),
+            loads: Cell::new(0),
+        }
+    }
+
+    /// Get the directory which contains the vendor configuration directory.
+    pub fn config_home(&self) -> Result<PathBuf> {
+        self.config_home
+            .clone()
+            .with_context(|| format!("No configuration directory for {}", self.app_id))
+    }
+
+    /// Monitor `file` and invalidate the cache whenever it changes.
+    fn monitor(&self, file: &Path, directory: bool) -> Result<gio::FileMonitor, glib::Error> {
+        trace!("Monitoring {} for {}", file.display(), self.app_id);
+        let file = gio::File::for_path(file);
+        let flags = gio::FileMonitorFlags::WATCH_MOVES;
+        let monitor = if directory {
+            file.monitor_directory(flags, None::<&gio::Cancellable>)
+        } else {
+            file.monitor_file(flags, None::<&gio::Cancellable>)
+        }?;
+        let cache = self.cache.clone();
+        let generation = self.generation.clone();
+        let app_id = self.app_id.clone();
+        monitor.connect_changed(move |_, file, _, event| match event {
+            // Wait for the hint that changes are done, and ignore attribute changes.
+            gio::FileMonitorEvent::Changed | gio::FileMonitorEvent::AttributeChanged => {}
+            _ => {
+                debug!(
+                    "{:?} on {:?}, invalidating recent projects of {}",
+                    event,
+                    file.path(),
+                    app_id
+                );
+                cache.replace(None);
+                generation.set(generation.get() + 1);
+            }
+        });
+        Ok(monitor)
+    }
+
+    /// Watch the `files` a worker read recent projects from.
+    ///
+    /// The files include the vendor directory, which changes when a new product version is
+    /// installed.
+    ///
+    /// Replace all previous monitors.  Return `false` if any monitor failed, or if any file
+    /// changed since the worker read it, because the monitors did not exist yet to notice
+    /// the change.
+    fn watch(&self, files: &[ReadFile]) -> bool {
+        let mut monitors = Vec::with_capacity(files.len());
+        let mut complete = true;
+        for file in files {
+            match self.monitor(&file.path, file.directory) {
+                Ok(monitor) => monitors.push(monitor),
+                Err(error) => {
+                    warn!(
+                        "Failed to monitor {} for {}: {}",
+                        file.path.display(),
+                        self.app_id,
+                        error
+                    );
+                    complete = false;
+                }
+            }
+        }
+        self.monitors.replace(monitors);
+        complete
+            && files.iter().all(|file| {
+                let changed = file.changed();
+                if changed {
+                    debug!(
+                        "{} changed while loading recent projects of {}",
+                        file.path.display(),
+                        self.app_id
+                    );
+                }
+                !changed
+            })
+    }
+
+    /// Start loading recent projects on a worker thread.
+    ///
+    /// Let the worker find and read the recent projects files.
+    fn start_loading(&self) -> Result<PendingLoad> {
+        let generation = self.generation.get();
+        let location = self
+            .config
+            .projects_location(&self.config_home()?, self.versions);
+        self.loads.set(self.loads.get() + 1);
+        debug!(
+            "Loading recent projects of {} ({} load(s))",
+            self.app_id,
+            self.loads.get()
+        );
+        let loader = self.loader.clone();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name(format!("load {}", self.app_id))
+            .spawn(move || {
+                let mounts = MountPoint::all();
+                // Remember modification times before reading, to notice changes until the
+                // source watches the files.
+                let vendor_dir = ReadFile::before_reading(location.vendor_dir.clone(), true);
+                let files = location.recent_projects_files();
+                let read = std::iter::once(vendor_dir)
+                    .chain(
+                        files
+                            .iter()
+                            .map(|file| ReadFile::before_reading(file.clone(), false)),
+                    )
+                    .collect();
+                let items = Arc::new(loader.load_recent_items(&files, &mounts));
+                let loaded = LoadedItems {
+                    hidden: loader.hidden_items(&items, &mounts),
+                    items,
+                    read: Some(read),
+                };
+                // The source may have stopped waiting for the result in the meantime.
+                let _ = sender.send(Ok(loaded));
+            })
+            .with_context(|| format!("Failed to start loading projects of {}", self.app_id))?;
+        Ok(PendingLoad {
+            generation,
+            receiver,
+        })
+    }
+
+    /// Get cached recent projects, if the configuration didn't change since the last load.
+    ///
+    /// Check again which projects no longer exist, because the cache does not notice deleted
+    /// or unmounted project directories.  This only reads the mount table, and never accesses
+    /// projects on slow mounts.
+    fn cached_items(&self) -> Option<IdMap<AppLaunchItem>> {
+        let items = self.cache.borrow().clone()?;
+        debug!("Using cached recent projects for {}", self.app_id);
+        let loaded = LoadedItems {
+            hidden: self.loader.hidden_items(&items, &MountPoint::all()),
+            items,
+            read: None,
+        };
+        let items = loaded.launch_items();
+        self.snapshot.replace(Some(loaded));
+        Some(items)
+    }
+
+    /// Start loading recent projects unless they're being loaded already.
+    fn start_loading_if_idle(&self) -> Result<()> {
+        if self.pending.borrow().is_none() {
+            let pending = self.start_loading()?;
+            self.pending.replace(Some(pending));
+        } else {
+            debug!("Still loading recent projects of {}", self.app_id);
+        }
+        Ok(())
+    }
+
+    /// Get the recent projects currently being loaded.
+    ///
+    /// Wait until `deadline`, or until loaded if `None`.  If loading takes longer, return the
+    /// results of a previous load if any, or no results otherwise.
+    fn wait_for_items(&self, deadline: Option<Instant>) -> Result<IdMap<AppLaunchItem>> {
+        let has_snapshot = self.snapshot.borrow().is_some();
+        let received = match self.pending.borrow().as_ref() {
+            None => return Err(anyhow!("Not loading projects of {}", self.app_id)),
+            Some(pending) => match deadline {
+                None => pending
+                    .receiver
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+                Some(deadline) => pending
+                    .receiver
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            },
+        };
+        match received {
+            Err(RecvTimeoutError::Timeout) if has_snapshot => {
+                debug!(
+                    "Still loading recent projects of {}, using previous projects",
+                    self.app_id
+                );
+                Ok(self.snapshot_items())
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                warn!(
+                    "Loading recent projects of {} takes longer than {:?}",
+                    self.app_id, self.load_timeout
+                );
+                Ok(IndexMap::new())
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                self.pending.replace(None);
+                Err(anyhow!("Loading recent projects of {} failed", self.app_id))
+            }
+            Ok(result) => {
+                let pending = self.pending.replace(None).unwrap();
+                let loaded = result?;
+                let watched = loaded.read.as_ref().map_or(true, |files| self.watch(files));
+                if watched && pending.generation == self.generation.get() {
+                    self.cache.replace(Some(loaded.items.clone()));
+                }
+                let items = loaded.launch_items();
+                self.snapshot.replace(Some(loaded));
+                Ok(items)
+            }
+        }
+    }
+
+    /// Get search results for the recent projects of the last successful load.
+    ///
+    /// Never load recent projects, and never touch the cache.
+    pub fn snapshot_items(&self) -> IdMap<AppLaunchItem> {
+        self.snapshot
+            .borrow()
+            .as_ref()
+            .map(LoadedItems::launch_items)
+            .unwrap_or_default()
+    }
+
+    /// Find recent items.
+    ///
+    /// Use cached items if possible; otherwise load recent projects, and wait for them until
+    /// `deadline`, or until loaded if `None`.
+    ///
+    /// Remember the error if loading recent projects fails.
+    fn find_recent_items_until(&self, deadline: Option<Instant>) -> Result<IdMap<AppLaunchItem>> {
+        let result = match self.cached_items() {
+            Some(items) => Ok(items),
+            None => self
+                .start_loading_if_idle()
+                .and_then(|_| self.wait_for_items(deadline)),
+        };
+        self.last_error
+            .replace(result.as_ref().err().map(|error| format!("{:#}", error)));
+        result
+    }
+}
+
+impl<'a> ItemsSource<AppLaunchItem> for JetbrainsProjectsSource<'a> {
+    type Err = anyhow::Error;
+
+    /// Find recent items.
+    ///
+    /// Reuse loaded projects if the configuration didn't change since the last call.  Otherwise
+    /// load recent projects on a worker thread, and monitor the configuration for changes;
+    /// wait for the projects at most for the load timeout, and return the projects of the last
+    /// successful load if loading takes longer.  Check whether projects still exist on every
+    /// call, because the cache does not notice deleted or unmounted project directories.
+    ///
+    /// Remember the error if loading recent projects fails.
+    fn find_recent_items(&self) -> Result<IdMap<AppLaunchItem>, Self::Err> {
+        self.find_recent_items_until(
+            self.load_timeout
+                .map(|load_timeout| Instant::now() + load_timeout),
+        )
+    }
+
+    /// Describe the recent project with the given `id` from the last successful load.
+    ///
+    /// Describe projects only when they're shown, and not when loading them, because
+    /// descriptions may read from the project directory, e.g. the Git branch.
+    fn describe_item(&self, id: &str) -> Option<String> {
+        let snapshot = self.snapshot.borrow();
+        let item = snapshot.as_ref()?.items.get(id)?;
+        Some(self.loader.describe_item(item, &MountPoint::all()))
+    }
+}
+
+/// Merge recent project `items` of different IDEs, along with the origin of every item.
+///
+/// Deduplicate items by their target, and keep the item which was used last, so that a
+/// project opens in the IDE which opened it last.  Keep the order of first occurrence.
+fn merge_project_items<O, I: IntoIterator<Item = (O, AppLaunchItem)>>(
+    items: I,
+) -> IdMap<(O, AppLaunchItem)> {
+    let mut merged: IdMap<(O, AppLaunchItem)> = IndexMap::new();
+    for (origin, item) in items {
+        let id = format!("jetbrains-recent-project-all-{}", item.target.description());
+        match merged.get_mut(&id) {
+            Some(existing) => {
+                if existing.1.last_used < item.last_used {
+                    *existing = (origin, item);
+                }
+            }
+            None => {
+                merged.insert(id, (origin, item));
+            }
+        }
+    }
+    merged
+}
+
+/// Recent projects of all IDEs.
+pub struct AllProjectsSource<'a> {
+    /// The sources of recent projects of all IDEs, along with the app of each IDE.
+    pub sources: Vec<(gio::DesktopAppInfo, Rc<JetbrainsProjectsSource<'a>>)>,
+    /// The index of the source and the ID in the source of every item of the last search.
+    origins: RefCell<HashMap<String, (usize, String)>>,
+}
+
+impl<'a> AllProjectsSource<'a> {
+    /// Create a source for recent projects of all IDEs from the `sources` of each IDE.
+    pub fn new(sources: Vec<(gio::DesktopAppInfo, Rc<JetbrainsProjectsSource<'a>>)>) -> Self {
+        Self {
+            sources,
+            origins: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Merge the `items` of every source, given by the index of the source.
+    ///
+    /// Launch every item with the app of its source, and return every merged item along with
+    /// the index of its source and its ID in the source, see [`merge_project_items`].
+    fn merge_items<I>(&self, items: I) -> IdMap<((usize, String), AppLaunchItem)>
+    where
+        I: IntoIterator<Item = (usize, IdMap<AppLaunchItem>)>,
+    {
+        merge_project_items(items.into_iter().flat_map(|(index, source_items)| {
+            let app = &self.sources[index].0;
+            source_items.into_iter().map(move |(id, item)| {
+                let item = AppLaunchItem {
+                    app: Some(app.clone()),
+                    ..item
+                };
+                ((index, id), item)
+            })
+        }))
+    }
+
+    /// Get the merged recent projects of the last successful load of every source.
+    ///
+    /// Never load recent projects, and never touch the origins of the last search.
+    pub fn snapshot_items(&self) -> IdMap<((usize, String), AppLaunchItem)> {
+        self.merge_items(
+            self.sources
+                .iter()
+                .enumerate()
+                .map(|(index, (_, source))| (index, source.snapshot_items())),
+        )
+    }
+}
+
+impl<'a> ItemsSource<AppLaunchItem> for AllProjectsSource<'a> {
+    type Err = anyhow::Error;
+
+    /// Find recent items of all IDEs.
+    ///
+    /// Merge projects which were opened in different IDEs, and launch every project with the
+    /// IDE which opened it last.  Skip IDEs whose recent projects fail to load.
+    fn find_recent_items(&self) -> Result<IdMap<AppLaunchItem>, Self::Err> {
+        // Start loading projects of all IDEs first, to wait for all IDEs at once.  Errors
+        // show again when getting the projects of each IDE below.
+        for (_, source) in &self.sources {
+            let _ = source.start_loading_if_idle();
+        }
+        let deadline = Instant::now() + LOAD_TIMEOUT;
+        let mut items = Vec::new();
+        for (index, (_, source)) in self.sources.iter().enumerate() {
+            match source.find_recent_items_until(Some(deadline)) {
+                Ok(source_items) => items.push((index, source_items)),
+                Err(error) => warn!("Skipping recent projects of {}: {:#}", source.app_id, error),
+            }
+        }
+        let merged = self.merge_items(items);
+        info!("Found {} project(s) of all IDEs", merged.len());
+        let mut origins = HashMap::with_capacity(merged.len());
+        let items = merged
+            .into_iter()
+            .map(|(id, (origin, item))| {
+                origins.insert(id.clone(), origin);
+                (id, item)
+            })
+            .collect();
+        self.origins.replace(origins);
+        Ok(items)
+    }
+
+    /// Describe the item with the given `id` with the source of the IDE which opened it last.
+    fn describe_item(&self, id: &str) -> Option<String> {
+        let origins = self.origins.borrow();
+        let (index, source_id) = origins.get(id)?;
+        self.sources[*index].1.describe_item(source_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::time::UNIX_EPOCH;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::recent::tests::recent_project;
+    use crate::PROVIDERS;
+
+    #[test]
+    fn solution_item() {
+        let solution = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join("tests")
+            .join("projects")
+            .join("solution")
+            .join("Foo.sln");
+        let loader = ProjectsLoader {
+            app_id: "jetbrains-rider.desktop".to_string(),
+            missing_projects: MissingProjects::Hide,
+            description: DescriptionTemplate::from_str("{directory}").unwrap(),
+        };
+        let project = recent_project(&solution.to_string_lossy());
+        let item = loader.project_item(project.clone(), &[]).unwrap();
+        assert_eq!(item.name, "Foo Service");
+        assert_eq!(
+            item.icon,
+            Some(
+                solution
+                    .with_file_name(".idea")
+                    .join(".idea.Foo")
+                    .join(".idea")
+                    .join("icon.svg")
+            )
+        );
+        assert_eq!(
+            loader.describe(&project, &[]),
+            solution.parent().unwrap().display().to_string()
+        );
+    }
+
+    #[test]
+    fn ssh_project_item() {
+        let loader = ProjectsLoader {
+            app_id: "jetbrains-gateway.desktop".to_string(),
+            missing_projects: MissingProjects::Hide,
+            description: DescriptionTemplate::from_str("{path}").unwrap(),
+        };
+        let project = recent_project("ssh://foo@example.com/home/foo/dev/mdcat");
+        let item = loader.project_item(project, &[]).unwrap();
+        assert_eq!(item.name, "mdcat");
+        assert_eq!(
+            item.target,
+            AppLaunchTarget::Uri(
+                "jetbrains-gateway://connect#type=ssh&deploy=false&host=example.com&port=22&user=foo&projectPath=%2Fhome%2Ffoo%2Fdev%2Fmdcat".to_string()
+            )
+        );
+        assert_eq!(item.icon, None);
+    }
+
+    #[test]
+    fn project_item_on_slow_mount() {
+        let solution = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join("tests")
+            .join("projects")
+            .join("solution")
+            .join("Foo.sln");
+        let loader = ProjectsLoader {
+            app_id: "jetbrains-rider.desktop".to_string(),
+            missing_projects: MissingProjects::Hide,
+            description: DescriptionTemplate::from_str("{directory}").unwrap(),
+        };
+        let project = recent_project(&solution.to_string_lossy());
+        let mounts = [MountPoint {
+            path: solution.parent().unwrap().to_path_buf(),
+            slow: true,
+        }];
+        let item = loader.project_item(project, &mounts).unwrap();
+        assert_eq!(item.name, "Foo");
+        assert_eq!(item.icon, None);
+    }
+
+    fn loaded_items() -> LoadedItems {
+        let project = recent_project("/home/foo/dev/mdcat");
+        let mut items = IndexMap::new();
+        items.insert(
+            "mdcat".to_string(),
+            ProjectItem {
+                project,
+                name: "mdcat".to_string(),
+                target: AppLaunchTarget::File("/home/foo/dev/mdcat".to_string()),
+                icon: None,
+            },
+        );
+        LoadedItems {
+            items: Arc::new(items),
+            hidden: HashSet::new(),
+            read: None,
+        }
+    }
+
+    #[test]
+    fn hide_or_mark_missing_projects() {
+        let loaded = loaded_items();
+        let item = &loaded.items["mdcat"];
+        let mut loader = ProjectsLoader {
+            app_id: "jetbrains-idea.desktop".to_string(),
+            missing_projects: MissingProjects::Hide,
+            description: DescriptionTemplate::default(),
+        };
+        assert_eq!(
+            loader.hidden_items(&loaded.items, &[]),
+            HashSet::from(["mdcat".to_string()])
+        );
+        assert_eq!(loader.describe_item(item, &[]), "/home/foo/dev/mdcat");
+
+        loader.missing_projects = MissingProjects::Show;
+        assert!(loader.hidden_items(&loaded.items, &[]).is_empty());
+        assert_eq!(
+            loader.describe_item(item, &[]),
+            "missing: /home/foo/dev/mdcat"
+        );
+
+        let mounts = [MountPoint {
+            path: PathBuf::from("/home/foo"),
+            slow: true,
+        }];
+        assert_eq!(loader.describe_item(item, &mounts), "/home/foo/dev/mdcat");
+    }
+
+    fn pending_source() -> (
+        JetbrainsProjectsSource<'static>,
+        mpsc::Sender<Result<LoadedItems>>,
+    ) {
+        let source = JetbrainsProjectsSource::new(
+            "jetbrains-idea.desktop".to_string(),
+            PROVIDERS[0].config,
+            None,
+            VersionSelection::Latest,
+            MissingProjects::Hide,
+            DescriptionTemplate::default(),
+            Some(Duration::from_millis(10)),
+        );
+        let (sender, receiver) = mpsc::channel();
+        source.pending.replace(Some(PendingLoad {
+            generation: 0,
+            receiver,
+        }));
+        (source, sender)
+    }
+
+    fn deadline() -> Option<Instant> {
+        Some(Instant::now() + Duration::from_millis(10))
+    }
+
+    #[test]
+    fn wait_for_loaded_items() {
+        let (source, sender) = pending_source();
+        sender.send(Ok(loaded_items())).unwrap();
+        let items = source.wait_for_items(deadline()).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items["mdcat"].description, None);
+        assert!(source.pending.borrow().is_none());
+        assert!(source.cache.borrow().is_some());
+        assert_eq!(source.snapshot.borrow().as_ref(), Some(&loaded_items()));
+        assert_eq!(
+            source.describe_item("mdcat").as_deref(),
+            Some("/home/foo/dev/mdcat")
+        );
+        assert_eq!(source.describe_item("foo"), None);
+    }
+
+    #[test]
+    fn wait_for_items_watches_read_files() {
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        let (source, sender) = pending_source();
+        let loaded = LoadedItems {
+            read: Some(vec![ReadFile::before_reading(manifest.clone(), false)]),
+            ..loaded_items()
+        };
+        sender.send(Ok(loaded)).unwrap();
+        assert_eq!(source.wait_for_items(deadline()).unwrap().len(), 1);
+        assert_eq!(source.monitors.borrow().len(), 1);
+        assert!(source.cache.borrow().is_some());
+    }
+
+    #[test]
+    fn wait_for_items_does_not_cache_outdated_items() {
+        let (source, sender) = pending_source();
+        source.generation.set(1);
+        sender.send(Ok(loaded_items())).unwrap();
+        assert_eq!(source.wait_for_items(deadline()).unwrap().len(), 1);
+        assert!(source.cache.borrow().is_none());
+
+        // The file changed after the worker read it, but before the source watched it.
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        let (source, sender) = pending_source();
+        let loaded = LoadedItems {
+            read: Some(vec![ReadFile {
+                path: manifest,
+                directory: false,
+                modified: Some(UNIX_EPOCH),
+            }]),
+            ..loaded_items()
+        };
+        sender.send(Ok(loaded)).unwrap();
+        assert_eq!(source.wait_for_items(deadline()).unwrap().len(), 1);
+        assert!(source.cache.borrow().is_none());
+    }
+
+    #[test]
+    fn wait_for_first_items_until_deadline() {
+        let (source, sender) = pending_source();
+        assert!(source.wait_for_items(deadline()).unwrap().is_empty());
+        assert!(source.pending.borrow().is_some());
+
+        drop(sender);
+        assert!(source.wait_for_items(deadline()).is_err());
+        assert!(source.pending.borrow().is_none());
+    }
+
+    /// Create an empty configuration directory for a test with the given `name`.
+    fn temp_config_home(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!(
+            "gnome-search-providers-jetbrains-{}-{}",
+            name,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(root.join("JetBrains").join("CLion2021.1").join("options"))
+            .unwrap();
+        root
+    }
+
+    /// Write the recent projects of CLion in `config_home`, for project directories with the
+    /// given `names` in `config_home`.
+    fn write_recent_projects(config_home: &Path, names: &[&str]) {
+        let entries = names
+            .iter()
+            .map(|name| {
+                let project = config_home.join(name);
+                std::fs::create_dir_all(&project).unwrap();
+                format!("<entry key=\"{}\" />", project.display())
+            })
+            .collect::<String>();
+        let data = format!(
+            r#"<application><component name="RecentProjectsManager"><option name="additionalInfo"><map>{}</map></option></component></application>"#,
+            entries
+        );
+        let options = config_home
+            .join("JetBrains")
+            .join("CLion2021.1")
+            .join("options");
+        std::fs::write(options.join("recentProjects.xml"), data).unwrap();
+    }
+
+    /// Create a source for CLion projects in `config_home`, which waits until projects are
+    /// loaded.
+    fn clion_source(config_home: &Path) -> JetbrainsProjectsSource<'static> {
+        JetbrainsProjectsSource::new(
+            "jetbrains-clion.desktop".to_string(),
+            PROVIDERS[0].config,
+            Some(config_home.to_path_buf()),
+            VersionSelection::Latest,
+            MissingProjects::Hide,
+            DescriptionTemplate::default(),
+            None,
+        )
+    }
+
+    /// Find the names of all recent projects of `source`, in alphabetical order.
+    fn project_names(source: &JetbrainsProjectsSource) -> Vec<String> {
+        let mut names = source
+            .find_recent_items()
+            .unwrap()
+            .into_iter()
+            .map(|(_, item)| item.name)
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn reuse_cached_projects() {
+        let root = temp_config_home("cache");
+        write_recent_projects(&root, &["mdcat"]);
+        let context = glib::MainContext::new();
+        let (names, loads) = context.with_thread_default(|| {
+            let source = clion_source(&root);
+            let names = (project_names(&source), project_names(&source));
+            (names, source.loads.get())
+        });
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(names.0, vec!["mdcat"]);
+        assert_eq!(names.1, vec!["mdcat"]);
+        assert_eq!(loads, 1);
+    }
+
+    #[test]
+    fn reload_recent_projects_after_change() {
+        let root = temp_config_home("reload");
+        write_recent_projects(&root, &["mdcat"]);
+        let context = glib::MainContext::new();
+        let (names, loads) = context.with_thread_default(|| {
+            let source = clion_source(&root);
+            let before = project_names(&source);
+            assert!(source.cache.borrow().is_some());
+
+            write_recent_projects(&root, &["mdcat", "gnome-search"]);
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while source.cache.borrow().is_some() && Instant::now() < deadline {
+                if !context.iteration(false) {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+            }
+            assert!(source.cache.borrow().is_none(), "Cache not invalidated");
+            ((before, project_names(&source)), source.loads.get())
+        });
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(names.0, vec!["mdcat"]);
+        assert_eq!(names.1, vec!["gnome-search", "mdcat"]);
+        assert_eq!(loads, 2);
+    }
+
+    #[test]
+    fn use_previous_items_until_loaded() {
+        let (source, sender) = pending_source();
+        source.snapshot.replace(Some(loaded_items()));
+        assert_eq!(
+            source.wait_for_items(deadline()).unwrap(),
+            loaded_items().launch_items()
+        );
+        assert!(source.pending.borrow().is_some());
+
+        let loaded = LoadedItems {
+            hidden: HashSet::from(["mdcat".to_string()]),
+            ..loaded_items()
+        };
+        let worker = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            sender.send(Ok(loaded)).unwrap();
+        });
+        let far = Some(Instant::now() + Duration::from_secs(60));
+        assert!(source.wait_for_items(far).unwrap().is_empty());
+        assert!(source.pending.borrow().is_none());
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn merge_project_items_keeps_last_used() {
+        let item = |path: &str, desktop_id, last_used: Option<u64>| {
+            (
+                desktop_id,
+                AppLaunchItem {
+                    name: path.to_string(),
+                    target: AppLaunchTarget::File(path.to_string()),
+                    last_used: last_used.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+                    keywords: vec![],
+                    description: None,
+                    icon: None,
+                    app: None,
+                },
+            )
+        };
+        let merged = merge_project_items(vec![
+            item("/home/foo/dev/mdcat", "idea", Some(1618242624)),
+            item("/home/foo/dev/bar", "idea", None),
+            item("/home/foo/dev/mdcat", "clion", Some(1618243465)),
+            item("/home/foo/dev/bar", "webstorm", Some(1618242624)),
+            item("/home/foo/dev/mdcat", "webstorm", None),
+        ]);
+        assert_eq!(
+            merged
+                .iter()
+                .map(|(id, (origin, _))| (id.as_str(), *origin))
+                .collect::<Vec<_>>(),
+            vec![
+                ("jetbrains-recent-project-all-/home/foo/dev/mdcat", "clion"),
+                ("jetbrains-recent-project-all-/home/foo/dev/bar", "webstorm"),
+            ]
+        );
+    }
+}